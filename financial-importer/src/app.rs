@@ -1,5 +1,44 @@
+use std::fmt;
+
 // Common constants for the app
 pub static DEFAULT_CONFIG_FILE_NAME: &str = "config.toml";
+// Every TOML file in this directory next to the config file is included.
+pub static CONFIG_INCLUDE_DIRECTORY_NAME: &str = "conf.d";
 pub static APP_NAME: &str = "financial-importer";
+// Kept in the platform's data directory unless given.
+pub static DEFAULT_HISTORY_FILE_NAME: &str = "import-history.csv";
 pub static LOG_ENV_VAR: &str = "FINANCIAL_IMPORTER_LOG";
 pub static VALIDATION_LOG_LEVEL: &str = "trace";
+
+// The reasons a run fails with its own documented exit code, attached to
+// errors with `wrap_err`. Any other error exits with 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Failure {
+    InvalidConfiguration,
+    ParseErrors,
+    FallbackUsed,
+    PartialFailure,
+}
+
+impl Failure {
+    #[must_use]
+    pub fn exit_code(self) -> u8 {
+        match self {
+            Failure::InvalidConfiguration => 2,
+            Failure::ParseErrors => 3,
+            Failure::FallbackUsed => 4,
+            Failure::PartialFailure => 5,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::InvalidConfiguration => write!(f, "The configuration is invalid."),
+            Failure::ParseErrors => write!(f, "Rows of the input failed to parse."),
+            Failure::FallbackUsed => write!(f, "Too many records used the fallback rule."),
+            Failure::PartialFailure => write!(f, "Some records could not be imported."),
+        }
+    }
+}
@@ -1,15 +1,45 @@
-use color_eyre::{eyre::eyre, Result, Section};
+use chrono::{Local, NaiveDate};
+use color_eyre::{
+    eyre::{eyre, WrapErr},
+    Result, Section,
+};
+use financial_importer::classifier::{classifier_model_path, CategoryClassifier};
+use financial_importer::history::{history_file_path, ImportHistory};
+use financial_importer::import_session::{ImportResult, ImportSession, ImportSource, RecordResult};
+use financial_importer::reconciliation::StatementReconciliation;
+use financial_importer::recurring::{RecurringCandidates, RecurringOptions};
+use financial_importer::review::{ReviewOutcome, ReviewSession};
+use financial_importer::rule_generation;
+use financial_importer::rule_generation::suggest::ACCOUNT_PLACEHOLDER;
+use financial_importer::rule_generation::{accounts_toml, transaction_rule_toml, LearningOptions};
 use financial_importer::source_record;
-use financial_importer::source_record::{write_source_records, SourceRecord};
+use financial_importer::source_record::{
+    describe_line_number, DuplicatePolicy, LoadPolicies, ParseErrorPolicy, SourceRecord,
+    SuspectedDuplicate,
+};
 use financial_importer::transaction_matcher;
-use financial_importer::transaction_matcher::{FinancialImporter, GeneratedLedgerEntry};
+use financial_importer::transaction_matcher::definitions::resolve_account_alias;
+use financial_importer::transaction_matcher::definitions::TransactionRuleConfiguration;
+use financial_importer::transaction_matcher::matcher::source_description;
+use financial_importer::transaction_matcher::{
+    ConfigurationEditor, FinancialImporter, GeneratedLedgerEntry, MatchExplanation, RuleMatch,
+};
+use financial_importer::transfers::{DetectedTransfer, StatementEntry, TransferDetection};
+use financial_importer::watch::{watch_directory, ImportedEntries};
 use financial_importer::{
-    app::{LOG_ENV_VAR, VALIDATION_LOG_LEVEL},
-    ledger_entry::{write_ledger_entries_file, LedgerEntry},
+    app::{Failure, LOG_ENV_VAR, VALIDATION_LOG_LEVEL},
+    ledger_entry::{
+        append_ledger_entries_file, read_journal, write_ledger_entries_file, LedgerEntry,
+    },
 };
 use log::trace;
-use std::iter::Map;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use std::time::Duration;
 use structopt::StructOpt;
 
 #[derive(Debug, StructOpt)]
@@ -23,6 +53,9 @@ struct App {
         parse(from_os_str)
     )]
     config_file: Option<PathBuf>,
+    /// The history of imported records, defaults to one in the platform's data directory.
+    #[structopt(long, env = "FINANCIAL_IMPORTER_HISTORY", parse(from_os_str))]
+    history_file: Option<PathBuf>,
     #[structopt(subcommand)]
     command: Command,
 }
@@ -39,12 +72,154 @@ enum Command {
     },
     /// Process a CSV file to produce entries.
     ProcessCSV {
+        /// The file format definition to use, detected from the input file's fingerprint if not given.
         #[structopt(long, short = "f")]
-        format_name: String,
+        format_name: Option<String>,
         #[structopt(long, short = "i", parse(from_os_str))]
         input_file: PathBuf,
         #[structopt(long, short = "u", parse(from_os_str))]
         unmatched_records_file: Option<PathBuf>,
+        /// What to do with rows that fail to parse: strict, quarantine or lenient.
+        #[structopt(long, default_value = "lenient")]
+        parse_errors: ParseErrorPolicy,
+        /// What to do with records repeating the date, description and amount of another: warn, drop or keep.
+        #[structopt(long, default_value = "warn")]
+        duplicates: DuplicatePolicy,
+        /// Where quarantined rows are written, defaults to the input file with a "-rejects" suffix.
+        #[structopt(long, parse(from_os_str))]
+        rejects_file: Option<PathBuf>,
+        /// Propose accounts for fallback entries with the classifier model next to the config file.
+        #[structopt(long)]
+        classify: bool,
+        /// Import records even if the import history shows they were imported before.
+        #[structopt(long)]
+        ignore_history: bool,
+        /// How the summary is printed: text, or json for scripts.
+        #[structopt(long, default_value = "text")]
+        summary_format: SummaryFormat,
+        /// Fail with exit code 4 if any record used the fallback rule.
+        #[structopt(long, conflicts_with = "max-fallback-ratio")]
+        fail_on_fallback: bool,
+        /// Fail with exit code 4 if more than this fraction of the records used the fallback rule.
+        #[structopt(long)]
+        max_fallback_ratio: Option<f64>,
+        #[structopt(
+            long,
+            short = "l",
+            parse(from_os_str),
+            default_value = "ledger-postings.dat"
+        )]
+        ledger_output_file: PathBuf,
+    },
+    /// List, show or undo the batches of records in the import history.
+    History {
+        #[structopt(subcommand)]
+        command: HistoryCommand,
+    },
+    /// Find recurring transactions, such as subscriptions, in the import history and input files.
+    Recurring {
+        /// The file format definition of the input files, detected from their fingerprints if not given.
+        #[structopt(long, short = "f")]
+        format_name: Option<String>,
+        /// Input files to look at besides the records in the import history.
+        #[structopt(long, short = "i", parse(from_os_str))]
+        input_files: Vec<PathBuf>,
+        /// What to do with rows that fail to parse: strict, quarantine or lenient.
+        #[structopt(long, default_value = "lenient")]
+        parse_errors: ParseErrorPolicy,
+        /// What to do with records repeating the date, description and amount of another: warn, drop or keep.
+        #[structopt(long, default_value = "warn")]
+        duplicates: DuplicatePolicy,
+        /// The fewest occurrences a recurring transaction needs.
+        #[structopt(long, default_value = "3")]
+        min_occurrences: usize,
+        /// How much amounts may change between occurrences, as a fraction, unless the new amount is kept.
+        #[structopt(long, default_value = "0.25")]
+        amount_tolerance: f64,
+        /// The date occurrences are expected by, for finding missed ones, defaults to today.
+        #[structopt(long)]
+        as_of: Option<NaiveDate>,
+        /// Write Ledger periodic transactions for the recurring transactions to this file.
+        #[structopt(long, short = "p", parse(from_os_str))]
+        periodic_output_file: Option<PathBuf>,
+    },
+    /// Process every statement file in a directory, detecting their formats, and archive them.
+    ImportDir {
+        #[structopt(long, short = "d", parse(from_os_str))]
+        input_dir: PathBuf,
+        /// Where imported files are moved to, defaults to an "archive" directory in the input directory.
+        #[structopt(long, parse(from_os_str))]
+        archive_dir: Option<PathBuf>,
+        /// What to do with rows that fail to parse: strict, quarantine or lenient.
+        #[structopt(long, default_value = "lenient")]
+        parse_errors: ParseErrorPolicy,
+        /// What to do with records repeating the date, description and amount of another: warn, drop or keep.
+        #[structopt(long, default_value = "warn")]
+        duplicates: DuplicatePolicy,
+        /// Write a Ledger file for each statement account, named with its alias, instead of one for all.
+        #[structopt(long)]
+        per_account: bool,
+        /// Propose accounts for fallback entries with the classifier model next to the config file.
+        #[structopt(long)]
+        classify: bool,
+        #[structopt(
+            long,
+            short = "l",
+            parse(from_os_str),
+            default_value = "ledger-postings.dat"
+        )]
+        ledger_output_file: PathBuf,
+    },
+    /// Watch a directory, appending the entries of each new statement file to a journal.
+    Watch {
+        #[structopt(long, short = "d", parse(from_os_str))]
+        input_dir: PathBuf,
+        /// What to do with rows that fail to parse: strict, quarantine or lenient.
+        #[structopt(long, default_value = "lenient")]
+        parse_errors: ParseErrorPolicy,
+        /// What to do with records repeating the date, description and amount of another: warn, drop or keep.
+        #[structopt(long, default_value = "warn")]
+        duplicates: DuplicatePolicy,
+        /// Milliseconds without changes to a file before it is processed.
+        #[structopt(long, default_value = "2000")]
+        settle_ms: u64,
+        /// Propose accounts for fallback entries with the classifier model next to the config file.
+        #[structopt(long)]
+        classify: bool,
+        /// The journal new entries are appended to, and checked against for records already imported.
+        #[structopt(long, short = "j", parse(from_os_str))]
+        journal_file: PathBuf,
+    },
+    /// Show which rules match a description, which one is used, and the entry it produces.
+    Explain {
+        #[structopt(long, short = "f")]
+        format_name: String,
+        description: String,
+        #[structopt(long, short = "a", default_value = "0", allow_hyphen_values = true)]
+        amount: f64,
+        /// The date of the record, defaulting to today.
+        #[structopt(long, short = "d")]
+        date: Option<NaiveDate>,
+        /// Propose an account with the classifier if the fallback rule is used.
+        #[structopt(long)]
+        classify: bool,
+    },
+    /// Step through the entries generated by the fallback rule to finalize them.
+    Review {
+        /// The file format definition to use, detected from the input file's fingerprint if not given.
+        #[structopt(long, short = "f")]
+        format_name: Option<String>,
+        #[structopt(long, short = "i", parse(from_os_str))]
+        input_file: PathBuf,
+        /// What to do with rows that fail to parse: strict, quarantine or lenient.
+        #[structopt(long, default_value = "lenient")]
+        parse_errors: ParseErrorPolicy,
+        /// What to do with records repeating the date, description and amount of another: warn, drop or keep.
+        #[structopt(long, default_value = "warn")]
+        duplicates: DuplicatePolicy,
+        /// Offer to save each changed category as a new transaction rule in the config file.
+        #[structopt(long)]
+        save_rules: bool,
         #[structopt(
             long,
             short = "l",
@@ -53,17 +228,123 @@ enum Command {
         )]
         ledger_output_file: PathBuf,
     },
+    /// Add an account alias to the config file.
+    AddAccount { alias: String, account_name: String },
+    /// Add a transaction rule after the existing rules of a format in the config file.
+    AddRule {
+        #[structopt(long, short = "f")]
+        format_name: String,
+        #[structopt(long)]
+        name: Option<String>,
+        #[structopt(long, short = "p")]
+        pattern: String,
+        /// Alias of the account the statement is for, defaulting to that of the fallback rule.
+        #[structopt(long)]
+        account1: Option<String>,
+        /// Alias of the account the record is categorized into.
+        #[structopt(long)]
+        account2: String,
+        #[structopt(long)]
+        payee: String,
+        #[structopt(long)]
+        needs_finalized: bool,
+        #[structopt(long)]
+        negate_first_amount: bool,
+    },
+    /// Propose transaction rules for records that no rule matched.
+    SuggestRules {
+        /// The file format definition to use, detected from the input file's fingerprint if not given.
+        #[structopt(long, short = "f")]
+        format_name: Option<String>,
+        #[structopt(long, short = "i", parse(from_os_str))]
+        input_file: PathBuf,
+        /// What to do with rows that fail to parse: strict, quarantine or lenient.
+        #[structopt(long, default_value = "lenient")]
+        parse_errors: ParseErrorPolicy,
+        /// What to do with records repeating the date, description and amount of another: warn, drop or keep.
+        #[structopt(long, default_value = "warn")]
+        duplicates: DuplicatePolicy,
+        /// Only suggest rules matching at least this many records.
+        #[structopt(long, default_value = "2")]
+        min_records: usize,
+        /// Write the suggestions here instead of to stdout.
+        #[structopt(long, short = "o", parse(from_os_str))]
+        output_file: Option<PathBuf>,
+    },
+    /// Generate transaction rules from the entries of an already categorized Ledger journal.
+    LearnRules {
+        #[structopt(long, short = "f")]
+        format_name: String,
+        #[structopt(long, short = "j", parse(from_os_str))]
+        journal_file: PathBuf,
+        /// Only generate rules seen in at least this many entries.
+        #[structopt(long, default_value = "2")]
+        min_occurrences: usize,
+        /// Only generate rules whose share of entries going to the same account is at least this.
+        #[structopt(long, default_value = "0.8")]
+        min_confidence: f64,
+        /// Prefixes of the accounts that rules categorize into.
+        #[structopt(long, default_value = "Expenses:,Income:", use_delimiter = true)]
+        category_prefixes: Vec<String>,
+        /// Write the rules here instead of to stdout.
+        #[structopt(long, short = "o", parse(from_os_str))]
+        output_file: Option<PathBuf>,
+    },
+    /// Train the fallback classifier on a categorized Ledger journal, saving it next to the config file.
+    TrainClassifier {
+        #[structopt(long, short = "j", parse(from_os_str))]
+        journal_file: PathBuf,
+        /// Prefixes of the accounts that records are categorized into.
+        #[structopt(long, default_value = "Expenses:,Income:", use_delimiter = true)]
+        category_prefixes: Vec<String>,
+    },
+    /// Check that a statement's records sum from its opening to its closing balance.
+    Reconcile {
+        #[structopt(long, short = "i", parse(from_os_str))]
+        input_file: PathBuf,
+        #[structopt(long, short = "o", allow_hyphen_values = true)]
+        opening_balance: f64,
+        #[structopt(long, short = "b", allow_hyphen_values = true)]
+        closing_balance: f64,
+    },
 }
 
-fn main() -> Result<()> {
-    color_eyre::install()?;
+#[derive(Debug, StructOpt)]
+#[structopt(rename_all = "kebab-case")]
+enum HistoryCommand {
+    /// List the import batches.
+    List,
+    /// Show the records of an import batch.
+    Show { batch_id: u64 },
+    /// Forget the records of an import batch, so that they are imported again.
+    Undo { batch_id: u64 },
+}
 
-    let app: App = App::from_args();
+fn main() -> ExitCode {
+    match run(App::from_args()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {:?}", error);
+            ExitCode::from(
+                error
+                    .downcast_ref::<Failure>()
+                    .map_or(1, |failure| failure.exit_code()),
+            )
+        }
+    }
+}
+
+fn run(app: App) -> Result<()> {
+    color_eyre::install()?;
 
     initialize_logging(&app);
 
     // Load the configuration
-    let importer: FinancialImporter = transaction_matcher::load_configuration(app.config_file)?;
+    let config_file: PathBuf = transaction_matcher::config_file_path(app.config_file)
+        .wrap_err(Failure::InvalidConfiguration)?;
+    let mut importer: FinancialImporter =
+        transaction_matcher::load_configuration(Some(config_file.clone()))
+            .wrap_err(Failure::InvalidConfiguration)?;
 
     // Now, dispatch based on the command
     match app.command {
@@ -78,116 +359,1596 @@ fn main() -> Result<()> {
             format_name,
             input_file,
             unmatched_records_file,
+            parse_errors,
+            duplicates,
+            rejects_file,
+            classify,
+            ignore_history,
+            summary_format,
+            fail_on_fallback,
+            max_fallback_ratio,
             ledger_output_file,
-        } => process_csv(
+        } => {
+            if classify {
+                importer.classifier = Some(CategoryClassifier::load(&classifier_model_path(
+                    &config_file,
+                ))?);
+            }
+
+            let format_name = importer.format_for_file(format_name, &input_file)?;
+            let mut history = ImportHistory::load(&history_file_path(app.history_file)?)?;
+            process_csv(
+                &importer,
+                format_name.as_str(),
+                &input_file,
+                LoadPolicies {
+                    parse_errors,
+                    duplicates,
+                },
+                &mut history,
+                ignore_history,
+                ProcessCsvOptions {
+                    unmatched_records_file,
+                    rejects_file,
+                    ledger_output_file,
+                    summary_format,
+                    max_fallback_ratio: if fail_on_fallback {
+                        Some(0.0)
+                    } else {
+                        max_fallback_ratio
+                    },
+                },
+            )?
+        }
+        Command::Recurring {
+            format_name,
+            input_files,
+            parse_errors,
+            duplicates,
+            min_occurrences,
+            amount_tolerance,
+            as_of,
+            periodic_output_file,
+        } => {
+            let history = ImportHistory::load(&history_file_path(app.history_file)?)?;
+            recurring(
+                &importer,
+                &history,
+                format_name,
+                &input_files,
+                LoadPolicies {
+                    parse_errors,
+                    duplicates,
+                },
+                &RecurringOptions {
+                    min_occurrences,
+                    amount_tolerance,
+                    as_of: as_of.unwrap_or_else(|| Local::now().date_naive()),
+                },
+                periodic_output_file.as_deref(),
+            )?
+        }
+        Command::History { command } => {
+            let mut history = ImportHistory::load(&history_file_path(app.history_file)?)?;
+            match command {
+                HistoryCommand::List => list_history(&history),
+                HistoryCommand::Show { batch_id } => show_history_batch(&history, batch_id)?,
+                HistoryCommand::Undo { batch_id } => undo_history_batch(&mut history, batch_id)?,
+            }
+        }
+        Command::ImportDir {
+            input_dir,
+            archive_dir,
+            parse_errors,
+            duplicates,
+            per_account,
+            classify,
+            ledger_output_file,
+        } => {
+            if classify {
+                importer.classifier = Some(CategoryClassifier::load(&classifier_model_path(
+                    &config_file,
+                ))?);
+            }
+
+            import_dir(
+                &importer,
+                &input_dir,
+                archive_dir,
+                LoadPolicies {
+                    parse_errors,
+                    duplicates,
+                },
+                per_account,
+                &ledger_output_file,
+            )?
+        }
+        Command::Watch {
+            input_dir,
+            parse_errors,
+            duplicates,
+            settle_ms,
+            classify,
+            journal_file,
+        } => {
+            if classify {
+                importer.classifier = Some(CategoryClassifier::load(&classifier_model_path(
+                    &config_file,
+                ))?);
+            }
+
+            watch(
+                &importer,
+                &input_dir,
+                LoadPolicies {
+                    parse_errors,
+                    duplicates,
+                },
+                Duration::from_millis(settle_ms),
+                &journal_file,
+            )?
+        }
+        Command::Explain {
+            format_name,
+            description,
+            amount,
+            date,
+            classify,
+        } => {
+            if classify {
+                importer.classifier = Some(CategoryClassifier::load(&classifier_model_path(
+                    &config_file,
+                ))?);
+            }
+
+            let record = SourceRecord {
+                date: date.unwrap_or_else(|| Local::now().date_naive()),
+                description,
+                amount,
+                original_amount: None,
+                original_currency: None,
+                fitid: None,
+                line_number: None,
+            };
+            explain(&importer, &format_name, &record)?
+        }
+        Command::Review {
+            format_name,
+            input_file,
+            parse_errors,
+            duplicates,
+            save_rules,
+            ledger_output_file,
+        } => review(
             &importer,
-            format_name.as_str(),
+            &config_file,
+            &importer.format_for_file(format_name, &input_file)?,
             &input_file,
-            unmatched_records_file,
+            LoadPolicies {
+                parse_errors,
+                duplicates,
+            },
+            save_rules,
             &ledger_output_file,
         )?,
+        Command::AddAccount {
+            alias,
+            account_name,
+        } => {
+            let mut editor = ConfigurationEditor::load(&config_file)?;
+            editor.add_account(&alias, &account_name)?;
+            let saved_paths = editor.save()?;
+            println!(
+                "Added account alias '{}' for '{}' to {}.",
+                alias,
+                account_name,
+                describe_paths(&saved_paths)
+            );
+        }
+        Command::AddRule {
+            format_name,
+            name,
+            pattern,
+            account1,
+            account2,
+            payee,
+            needs_finalized,
+            negate_first_amount,
+        } => {
+            let mut editor = ConfigurationEditor::load(&config_file)?;
+            editor.add_transaction_rule(
+                &format_name,
+                &TransactionRuleConfiguration {
+                    name,
+                    pattern_string: pattern,
+                    account1,
+                    account2,
+                    payee,
+                    needs_finalized: needs_finalized.then_some(true),
+                    negate_first_amount: negate_first_amount.then_some(true),
+                },
+            )?;
+            let saved_paths = editor.save()?;
+            println!(
+                "Added transaction rule to '{}' in {}.",
+                format_name,
+                describe_paths(&saved_paths)
+            );
+        }
+        Command::SuggestRules {
+            format_name,
+            input_file,
+            parse_errors,
+            duplicates,
+            min_records,
+            output_file,
+        } => suggest_rules(
+            &importer,
+            &importer.format_for_file(format_name, &input_file)?,
+            &input_file,
+            LoadPolicies {
+                parse_errors,
+                duplicates,
+            },
+            min_records,
+            output_file,
+        )?,
+        Command::LearnRules {
+            format_name,
+            journal_file,
+            min_occurrences,
+            min_confidence,
+            category_prefixes,
+            output_file,
+        } => learn_rules(
+            &importer,
+            &format_name,
+            &journal_file,
+            &LearningOptions {
+                category_account_prefixes: category_prefixes,
+                min_occurrences,
+                min_confidence,
+            },
+            output_file,
+        )?,
+        Command::TrainClassifier {
+            journal_file,
+            category_prefixes,
+        } => train_classifier(&importer, &config_file, &journal_file, &category_prefixes)?,
+        Command::Reconcile {
+            input_file,
+            opening_balance,
+            closing_balance,
+        } => reconcile(&input_file, opening_balance, closing_balance)?,
     }
 
     Ok(())
 }
 
+// Where `process-csv` writes its output, with the derived files defaulting to
+// names based on the input file, how it reports it, and when it fails.
+struct ProcessCsvOptions {
+    unmatched_records_file: Option<PathBuf>,
+    rejects_file: Option<PathBuf>,
+    ledger_output_file: PathBuf,
+    summary_format: SummaryFormat,
+    // The largest fraction of the imported records allowed to use the fallback rule.
+    max_fallback_ratio: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SummaryFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for SummaryFormat {
+    type Err = color_eyre::Report;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(eyre!(
+                "Unknown summary format '{}', expected one of: text, json.",
+                format
+            )),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProcessCsvSummary {
+    input_file: PathBuf,
+    format_name: String,
+    counts: SummaryCounts,
+    output_files: SummaryOutputFiles,
+    // The batch the imported records were recorded as, if any were imported.
+    history_batch_id: Option<u64>,
+    records: Vec<RecordResult>,
+    rejected_rows: Vec<RejectedRow>,
+}
+
+#[derive(Serialize)]
+struct SummaryCounts {
+    records: usize,
+    matched: usize,
+    fallback: usize,
+    errors: usize,
+    rejected: usize,
+    duplicates: usize,
+    already_imported: usize,
+    // Records imported again with the history ignored are not recorded twice.
+    recorded_in_history: usize,
+}
+
+#[derive(Serialize)]
+struct SummaryOutputFiles {
+    // Not written when every record was imported before, leaving the files of that import.
+    ledger: Option<PathBuf>,
+    unmatched_records: Option<PathBuf>,
+    // Only written when rows are quarantined.
+    rejects: Option<PathBuf>,
+    history: PathBuf,
+}
+
+#[derive(Serialize)]
+struct RejectedRow {
+    line_number: Option<u64>,
+    reason: String,
+}
+
+// Loads the input file and generates its entries, skipping the records the
+// history shows were imported before, and writing the records handled by the
+// fallback rule to the unmatched records file.
 fn process_csv(
     importer: &FinancialImporter,
     format_name: &str,
     input_file: &Path,
-    unmatched_records_file: Option<PathBuf>,
+    policies: LoadPolicies,
+    history: &mut ImportHistory,
+    ignore_history: bool,
+    ProcessCsvOptions {
+        unmatched_records_file,
+        rejects_file,
+        ledger_output_file,
+        summary_format,
+        max_fallback_ratio,
+    }: ProcessCsvOptions,
+) -> Result<()> {
+    let unmatched_records_path = get_derived_records_file_path(
+        unmatched_records_file,
+        input_file,
+        UNMATCHED_RECORDS_FILE_SUFFIX,
+    );
+    let rejects_path = get_derived_records_file_path(rejects_file, input_file, REJECTS_FILE_SUFFIX);
+    let mut session = ImportSession::new(importer, format_name);
+    if !ignore_history {
+        session = session.with_history(history);
+    }
+    let imported = session.import(ImportSource::File {
+        path: input_file,
+        policies,
+        rejects_path: &rejects_path,
+    })?;
+    report_duplicates(&imported.duplicates);
+    let has_new_records =
+        imported.records_count == 0 || imported.already_imported_count < imported.records_count;
+    if has_new_records {
+        imported.write_unmatched_records(&unmatched_records_path)?;
+        imported.write_ledger_entries(&ledger_output_file, &importer.formatting)?;
+    }
+
+    let ImportResult {
+        records_count,
+        rejected,
+        duplicates,
+        already_imported_count,
+        matched_entries,
+        fallback_entries,
+        imported_records,
+        record_results,
+        errors,
+        ..
+    } = imported;
+    let matched_count = matched_entries.len();
+    let unmatched_count = fallback_entries.len();
+
+    let history_batch = history.record_batch(
+        format_name,
+        input_file,
+        &ledger_output_file,
+        &imported_records.iter().collect::<Vec<_>>(),
+    )?;
+
+    let quarantined = policies.parse_errors == ParseErrorPolicy::Quarantine && !rejected.is_empty();
+    let summary = ProcessCsvSummary {
+        input_file: input_file.to_path_buf(),
+        format_name: String::from(format_name),
+        counts: SummaryCounts {
+            records: records_count,
+            matched: matched_count,
+            fallback: unmatched_count,
+            errors: errors.len(),
+            rejected: rejected.len(),
+            duplicates: duplicates.len(),
+            already_imported: already_imported_count,
+            recorded_in_history: history_batch.as_ref().map_or(0, |batch| batch.record_count),
+        },
+        output_files: SummaryOutputFiles {
+            ledger: has_new_records.then_some(ledger_output_file),
+            unmatched_records: has_new_records.then_some(unmatched_records_path),
+            rejects: quarantined.then_some(rejects_path),
+            history: history.path().to_path_buf(),
+        },
+        history_batch_id: history_batch.map(|batch| batch.batch_id),
+        records: record_results,
+        rejected_rows: rejected
+            .into_iter()
+            .map(|rejected_record| RejectedRow {
+                line_number: rejected_record.line_number,
+                reason: rejected_record.reason,
+            })
+            .collect(),
+    };
+
+    match summary_format {
+        SummaryFormat::Text => print_process_csv_summary(&summary, policies.duplicates),
+        SummaryFormat::Json => println!("{}", serde_json::to_string_pretty(&summary)?),
+    }
+
+    // The output is written either way, the most serious problem decides the exit code.
+    // Lenient parsing only reports the rejected rows, as it always has.
+    let counts = &summary.counts;
+    let imported_count = counts.matched + counts.fallback;
+    if !errors.is_empty() {
+        errors
+            .into_iter()
+            .fold(
+                Err(eyre!("One or more errors were reported!")),
+                color_eyre::Help::section,
+            )
+            .wrap_err(Failure::PartialFailure)
+    } else if counts.rejected > 0 && policies.parse_errors != ParseErrorPolicy::Lenient {
+        Err(eyre!(
+            "{} rows of '{}' failed to parse.",
+            counts.rejected,
+            input_file.to_str().unwrap()
+        ))
+        .wrap_err(Failure::ParseErrors)
+    } else if let Some(max_fallback_ratio) = max_fallback_ratio.filter(|&max_fallback_ratio| {
+        imported_count > 0 && counts.fallback as f64 / imported_count as f64 > max_fallback_ratio
+    }) {
+        Err(eyre!(
+            "{} of {} records used the fallback rule, more than the allowed {}%.",
+            counts.fallback,
+            imported_count,
+            max_fallback_ratio * 100.0
+        ))
+        .wrap_err(Failure::FallbackUsed)
+    } else {
+        Ok(())
+    }
+}
+
+fn print_process_csv_summary(summary: &ProcessCsvSummary, duplicates: DuplicatePolicy) {
+    let counts = &summary.counts;
+    let output_files = &summary.output_files;
+
+    println!("Summary: ");
+    println!(
+        "- Using the file format definition \"{}\".",
+        &summary.format_name
+    );
+    println!(
+        "- Loaded {} source records from file {}.",
+        counts.records,
+        summary.input_file.to_str().unwrap()
+    );
+    if let Some(rejects_path) = &output_files.rejects {
+        println!(
+            "- Rejected {} rows that failed to parse, written to the file {}.\n",
+            counts.rejected,
+            rejects_path.to_str().unwrap()
+        );
+    } else {
+        println!(
+            "- Rejected {} rows that failed to parse.\n",
+            counts.rejected
+        );
+    }
+    match duplicates {
+        DuplicatePolicy::Warn if counts.duplicates > 0 => println!(
+            "- Kept {} suspected duplicate records, listed above.\n",
+            counts.duplicates
+        ),
+        DuplicatePolicy::Drop if counts.duplicates > 0 => println!(
+            "- Dropped {} suspected duplicate records, listed above.\n",
+            counts.duplicates
+        ),
+        _ => (),
+    }
+    if counts.already_imported > 0 {
+        println!(
+            "- Skipped {} records imported before, according to the history {}.\n",
+            counts.already_imported,
+            output_files.history.to_str().unwrap()
+        );
+    }
+
+    if let (Some(ledger), Some(unmatched_records)) =
+        (&output_files.ledger, &output_files.unmatched_records)
+    {
+        println!(
+            "- Wrote {} Ledger entries to file {}.",
+            counts.matched + counts.fallback,
+            ledger.to_str().unwrap()
+        );
+        println!(
+            "   - {} Ledger entries generated from matching transaction rules.",
+            counts.matched
+        );
+        println!(
+            "   - {} Ledger entries generated using the fallback rule.\n",
+            counts.fallback
+        );
+        println!(
+            "- Wrote {} unmatched source records to the file {}.",
+            counts.fallback,
+            unmatched_records.to_str().unwrap()
+        );
+    } else {
+        println!(
+            "- No new records, so the Ledger and unmatched records files were left as they were."
+        );
+    }
+
+    if let Some(batch_id) = summary.history_batch_id {
+        println!(
+            "- Recorded {} imported records as batch {} in the history {}.",
+            counts.recorded_in_history,
+            batch_id,
+            output_files.history.to_str().unwrap()
+        );
+    }
+}
+
+static DEFAULT_ARCHIVE_DIRECTORY_NAME: &str = "archive";
+
+// How one file of a directory import went.
+enum DirectoryFileOutcome {
+    Imported {
+        format_name: String,
+        records_count: usize,
+        rejected_count: usize,
+        duplicate_count: usize,
+        matched_count: usize,
+        fallback_count: usize,
+    },
+    Failed {
+        format_name: Option<String>,
+        error: color_eyre::Report,
+    },
+}
+
+fn import_dir(
+    importer: &FinancialImporter,
+    input_dir: &Path,
+    archive_dir: Option<PathBuf>,
+    policies: LoadPolicies,
+    per_account: bool,
     ledger_output_file: &Path,
 ) -> Result<()> {
+    let archive_dir = archive_dir.unwrap_or_else(|| input_dir.join(DEFAULT_ARCHIVE_DIRECTORY_NAME));
+    let output_dir = ledger_output_file.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut input_files: Vec<PathBuf> = std::fs::read_dir(input_dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()?;
+    input_files.retain(|path| {
+        path.is_file()
+            && !path
+                .file_name()
+                .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+    });
+    input_files.sort();
+
+    // Entries by the alias of the account of the statements they came from
+    let mut entries_by_account: BTreeMap<String, Vec<LedgerEntry>> = BTreeMap::new();
+    let mut outcomes: Vec<(PathBuf, DirectoryFileOutcome)> = Vec::new();
+
+    for input_file in input_files {
+        let format_name = match importer.format_for_file(None, &input_file) {
+            Ok(format_name) => format_name,
+            Err(error) => {
+                outcomes.push((
+                    input_file,
+                    DirectoryFileOutcome::Failed {
+                        format_name: None,
+                        error,
+                    },
+                ));
+                continue;
+            }
+        };
+
+        // Derived files go next to the output, as the input file is archived
+        let output_relative_file = output_dir.join(input_file.file_name().unwrap());
+        let rejects_path =
+            get_derived_records_file_path(None, &output_relative_file, REJECTS_FILE_SUFFIX);
+        let imported = ImportSession::new(importer, &format_name)
+            .import(ImportSource::File {
+                path: &input_file,
+                policies,
+                rejects_path: &rejects_path,
+            })
+            .and_then(|imported| {
+                report_duplicates(&imported.duplicates);
+                imported.write_unmatched_records(&get_derived_records_file_path(
+                    None,
+                    &output_relative_file,
+                    UNMATCHED_RECORDS_FILE_SUFFIX,
+                ))?;
+                Ok(imported)
+            });
+
+        let outcome = match imported {
+            Ok(ImportResult { errors, .. }) if !errors.is_empty() => DirectoryFileOutcome::Failed {
+                format_name: Some(format_name),
+                error: errors.into_iter().fold(
+                    eyre!("Some records could not be turned into entries:"),
+                    |report, e| report.section(e),
+                ),
+            },
+            Ok(ImportResult {
+                records_count,
+                rejected,
+                duplicates,
+                mut matched_entries,
+                mut fallback_entries,
+                ..
+            }) => {
+                let matched_count = matched_entries.len();
+                let fallback_count = fallback_entries.len();
+                let statement_account = importer
+                    .matcher_for_format(&format_name)?
+                    .fallback_rule
+                    .account1
+                    .clone();
+                let account_entries = entries_by_account.entry(statement_account).or_default();
+                account_entries.append(&mut matched_entries);
+                account_entries.append(&mut fallback_entries);
+
+                DirectoryFileOutcome::Imported {
+                    format_name,
+                    records_count,
+                    rejected_count: rejected.len(),
+                    duplicate_count: duplicates.len(),
+                    matched_count,
+                    fallback_count,
+                }
+            }
+            Err(error) => DirectoryFileOutcome::Failed {
+                format_name: Some(format_name),
+                error,
+            },
+        };
+        outcomes.push((input_file, outcome));
+    }
+
+    let transfers = match &importer.transfer_detection {
+        Some(transfer_detection) => {
+            pair_transfers(importer, transfer_detection, &mut entries_by_account)?
+        }
+        None => Vec::new(),
+    };
+
+    let mut output_files: Vec<(PathBuf, usize)> = Vec::new();
+    if per_account {
+        for (account, mut entries) in entries_by_account {
+            let output_file =
+                get_derived_file_path(None, ledger_output_file, &format!("-{}", account));
+            entries.sort();
+            output_files.push((output_file.clone(), entries.len()));
+            write_ledger_entries_file(&output_file, entries, &importer.formatting)?;
+        }
+    } else {
+        let mut entries: Vec<LedgerEntry> = entries_by_account.into_values().flatten().collect();
+        entries.sort();
+        output_files.push((ledger_output_file.to_path_buf(), entries.len()));
+        write_ledger_entries_file(ledger_output_file, entries, &importer.formatting)?;
+    }
+
+    // Only archive once the entries are safely written
+    let imported_files: Vec<PathBuf> = outcomes
+        .iter()
+        .filter(|(_, outcome)| matches!(outcome, DirectoryFileOutcome::Imported { .. }))
+        .map(|(input_file, _)| input_file.clone())
+        .collect();
+    if !imported_files.is_empty() {
+        std::fs::create_dir_all(&archive_dir)?;
+    }
+    for input_file in &imported_files {
+        archive_file(input_file, &archive_dir)?;
+    }
+
     println!("Summary: ");
-    println!("- Using the file format definition \"{}\".", &format_name);
+    println!(
+        "{:<32} {:<16} {:>8} {:>8} {:>8} {:>8} {:>10}",
+        "File", "Format", "Records", "Matched", "Fallback", "Rejected", "Duplicates"
+    );
+    let mut failures = Vec::new();
+    for (input_file, outcome) in outcomes {
+        let file_name = input_file
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        match outcome {
+            DirectoryFileOutcome::Imported {
+                format_name,
+                records_count,
+                rejected_count,
+                duplicate_count,
+                matched_count,
+                fallback_count,
+            } => println!(
+                "{:<32} {:<16} {:>8} {:>8} {:>8} {:>8} {:>10}",
+                file_name,
+                format_name,
+                records_count,
+                matched_count,
+                fallback_count,
+                rejected_count,
+                duplicate_count
+            ),
+            DirectoryFileOutcome::Failed { format_name, error } => {
+                println!(
+                    "{:<32} {:<16} {:>8}",
+                    file_name,
+                    format_name.as_deref().unwrap_or("-"),
+                    "FAILED"
+                );
+                failures.push(error.wrap_err(format!("Could not import '{}'", file_name)));
+            }
+        }
+    }
+    println!();
+
+    if importer.transfer_detection.is_some() {
+        println!("- Paired {} transfers between statements:", transfers.len());
+        for (outflow, inflow) in &transfers {
+            println!("   - {}", outflow);
+            println!("     {}", inflow);
+        }
+    }
+    for (output_file, entries_count) in &output_files {
+        println!(
+            "- Wrote {} Ledger entries to file {}.",
+            entries_count,
+            output_file.to_str().unwrap()
+        );
+    }
+    println!(
+        "- Moved {} imported files to {}.",
+        imported_files.len(),
+        archive_dir.to_str().unwrap()
+    );
+
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        failures
+            .into_iter()
+            .map(|e| format!("{:#}", e))
+            .fold(
+                Err(eyre!(
+                    "One or more files could not be imported, and were left in place:"
+                )),
+                color_eyre::Help::section,
+            )
+            .wrap_err(Failure::PartialFailure)
+    }
+}
+
+fn recurring(
+    importer: &FinancialImporter,
+    history: &ImportHistory,
+    format_name: Option<String>,
+    input_files: &[PathBuf],
+    policies: LoadPolicies,
+    options: &RecurringOptions,
+    periodic_output_file: Option<&Path>,
+) -> Result<()> {
+    let mut records_by_format: BTreeMap<String, Vec<SourceRecord>> = BTreeMap::new();
+    for record in history.records() {
+        records_by_format
+            .entry(record.format_name.clone())
+            .or_default()
+            .push(record.source_record());
+    }
+    let history_count: usize = records_by_format.values().map(Vec::len).sum();
+
+    let mut input_count = 0;
+    for input_file in input_files {
+        let format_name = importer.format_for_file(format_name.clone(), input_file)?;
+        let records = load_input_records(input_file, policies)?;
+        let seen = history.seen_records(&format_name, &records);
+        let format_records = records_by_format.entry(format_name).or_default();
+        for (record, seen) in records.into_iter().zip(seen) {
+            if !seen {
+                input_count += 1;
+                format_records.push(record);
+            }
+        }
+    }
+
+    let mut candidates = RecurringCandidates::default();
+    for (format_name, records) in &records_by_format {
+        if importer.matcher_for_format(format_name).is_err() {
+            trace!(
+                "Skipping history records of format '{}', which is no longer defined.",
+                format_name
+            );
+            continue;
+        }
+        for record in records {
+            candidates.add(importer.ledger_entry_for_source_record(format_name, record)?);
+        }
+    }
+
+    let series = candidates.find_recurring(options);
+
+    println!(
+        "Looked at {} records from the import history and {} new records from input files.",
+        history_count, input_count
+    );
+    println!("Found {} recurring transactions:", series.len());
+    for recurring in &series {
+        let latest = recurring.latest();
+        println!(
+            "- {}: {}, {} occurrences from {} to {}, last {}.",
+            recurring.payee,
+            recurring.periodicity,
+            recurring.occurrences.len(),
+            recurring.occurrences[0].date,
+            latest.date,
+            importer.formatting.format_amount(&latest.amount)
+        );
+        for change in &recurring.price_changes {
+            println!(
+                "   - Price changed from {} to {} on {}.",
+                importer.formatting.format_amount(&change.from),
+                importer.formatting.format_amount(&change.to),
+                change.date
+            );
+        }
+        for missed_date in &recurring.missed_dates {
+            println!(
+                "   - Missed the occurrence expected around {}.",
+                missed_date
+            );
+        }
+        for irregular_date in &recurring.irregular_dates {
+            println!("   - Charged off schedule on {}.", irregular_date);
+        }
+        match recurring.overdue_dates.as_slice() {
+            [] => (),
+            [expected_date] => println!(
+                "   - Not seen since {}, missing the occurrence expected around {}.",
+                latest.date, expected_date
+            ),
+            [first, .., last] => println!(
+                "   - Not seen since {}, missing {} occurrences expected from {} to {}.",
+                latest.date,
+                recurring.overdue_dates.len(),
+                first,
+                last
+            ),
+        }
+    }
+
+    if let Some(periodic_output_file) = periodic_output_file {
+        let periodic_transactions: Vec<String> = series
+            .iter()
+            .map(|recurring| recurring.periodic_transaction(&importer.formatting))
+            .collect();
+        std::fs::write(periodic_output_file, periodic_transactions.join("\n"))?;
+        println!(
+            "\n- Wrote {} periodic transactions to file {}.",
+            series.len(),
+            periodic_output_file.to_str().unwrap()
+        );
+    }
 
-    let unmatched_records_path = get_unmatched_file_path(unmatched_records_file, input_file);
-    let records: Vec<SourceRecord> = source_record::load_source_records(input_file)?;
+    Ok(())
+}
+
+fn list_history(history: &ImportHistory) {
+    let batches = history.batches();
+    if batches.is_empty() {
+        println!(
+            "No imports recorded in the history {}.",
+            history.path().to_str().unwrap()
+        );
+        return;
+    }
+
+    println!(
+        "{:>6} {:<19} {:<16} {:>8}  Source file",
+        "Batch", "Imported at", "Format", "Records"
+    );
+    for batch in batches {
+        println!(
+            "{:>6} {:<19} {:<16} {:>8}  {}",
+            batch.batch_id,
+            batch.imported_at.format("%Y-%m-%d %H:%M:%S"),
+            batch.format_name,
+            batch.record_count,
+            batch.source_file
+        );
+    }
+}
+
+fn show_history_batch(history: &ImportHistory, batch_id: u64) -> Result<()> {
+    let records = history.batch_records(batch_id);
+    let Some(first) = records.first() else {
+        return Err(eyre!(
+            "No import batch with ID {} in the history.",
+            batch_id
+        ));
+    };
 
     println!(
-        "- Loaded {} source records from file {}.\n",
+        "Batch {}: {} records of {} imported with format \"{}\" at {}, written to {}.",
+        batch_id,
         records.len(),
-        input_file.to_str().unwrap()
+        first.source_file,
+        first.format_name,
+        first.imported_at.format("%Y-%m-%d %H:%M:%S"),
+        first.output_file
     );
+    for record in records {
+        match &record.fitid {
+            Some(fitid) => println!(
+                "- {} {} {} (ID {})",
+                record.date, record.description, record.amount, fitid
+            ),
+            None => println!("- {} {} {}", record.date, record.description, record.amount),
+        }
+    }
 
-    let (entries, errors): (Vec<_>, Vec<_>) = records
-        .iter()
-        .map(|record| importer.ledger_entry_for_source_record(format_name, record))
-        .partition(Result::is_ok);
+    Ok(())
+}
 
-    // This is going to be a little messy, but I need to do it this way to report everything
-    let (matched_entries, unmatched_entries): (Vec<_>, Vec<_>) = entries
-        .into_iter()
-        .map(Result::unwrap)
-        .partition(GeneratedLedgerEntry::is_from_matched_rule);
+fn undo_history_batch(history: &mut ImportHistory, batch_id: u64) -> Result<()> {
+    let removed = history.undo_batch(batch_id)?;
+    let first = &removed[0];
 
-    let (unmatched_entries, mut unmatched_records): (Vec<_>, Vec<_>) = unmatched_entries
+    println!(
+        "Removed batch {} of {} records of {} from the history, so they will be imported again.",
+        batch_id,
+        removed.len(),
+        first.source_file
+    );
+    println!(
+        "Their entries written to {} are not removed from it.",
+        first.output_file
+    );
+
+    Ok(())
+}
+
+// Replaces the entries that are the two sides of a transfer between
+// statement accounts with a single transfer entry, in the outflow's account,
+// returning descriptions of the paired sides.
+fn pair_transfers(
+    importer: &FinancialImporter,
+    transfer_detection: &TransferDetection,
+    entries_by_account: &mut BTreeMap<String, Vec<LedgerEntry>>,
+) -> Result<Vec<(String, String)>> {
+    let mut account_aliases: BTreeMap<String, String> = BTreeMap::new();
+    let mut statement_entries = Vec::new();
+    for (alias, entries) in std::mem::take(entries_by_account) {
+        let statement_account = resolve_account_alias(&importer.accounts, &alias)
+            .ok_or_else(|| eyre!("Account alias '{}' not found.", alias))?;
+        account_aliases.insert(statement_account.clone(), alias);
+        statement_entries.extend(entries.into_iter().map(|entry| StatementEntry {
+            statement_account: statement_account.clone(),
+            entry,
+        }));
+    }
+
+    let (unpaired, transfers) = transfer_detection.pair_transfers(statement_entries)?;
+
+    for StatementEntry {
+        statement_account,
+        entry,
+    } in unpaired
+    {
+        entries_by_account
+            .entry(account_aliases[&statement_account].clone())
+            .or_default()
+            .push(entry);
+    }
+
+    let describe_side = |side: &StatementEntry| {
+        format!(
+            "{} {} {}",
+            side.entry.date,
+            side.statement_account,
+            source_description(&side.entry).unwrap_or(side.entry.payee.as_str())
+        )
+    };
+    Ok(transfers
         .into_iter()
-        .map(GeneratedLedgerEntry::unwrap)
-        .unzip();
+        .map(
+            |DetectedTransfer {
+                 transfer_entry,
+                 outflow,
+                 inflow,
+             }| {
+                let descriptions = (
+                    format!("From: {}", describe_side(&outflow)),
+                    format!("To:   {}", describe_side(&inflow)),
+                );
+                entries_by_account
+                    .entry(account_aliases[&outflow.statement_account].clone())
+                    .or_default()
+                    .push(transfer_entry);
+                descriptions
+            },
+        )
+        .collect())
+}
+
+fn watch(
+    importer: &FinancialImporter,
+    input_dir: &Path,
+    policies: LoadPolicies,
+    settle_time: Duration,
+    journal_file: &Path,
+) -> Result<()> {
+    let mut imported = if journal_file.exists() {
+        ImportedEntries::from_entries(&read_journal(journal_file, &importer.formatting)?.entries)
+    } else {
+        ImportedEntries::default()
+    };
 
-    unmatched_records.sort();
-    write_source_records(&unmatched_records_path, &unmatched_records)?;
+    println!(
+        "Watching {} for statement files, appending to {}.",
+        input_dir.to_str().unwrap(),
+        journal_file.to_str().unwrap()
+    );
+
+    // The journal may not exist yet, so only its directory is canonicalized
+    let journal_dir = match journal_file.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let canonical_journal_file = journal_dir
+        .canonicalize()?
+        .join(journal_file.file_name().unwrap());
+
+    watch_directory(input_dir, settle_time, |input_files| {
+        for input_file in input_files {
+            if is_watch_output(&input_file, &canonical_journal_file) {
+                continue;
+            }
+            let file_name = input_file
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            if let Err(e) =
+                watch_import_file(importer, &input_file, policies, journal_file, &mut imported)
+            {
+                eprintln!(
+                    "{} Could not import '{}': {:#}",
+                    Local::now().format("%Y-%m-%d %H:%M:%S"),
+                    file_name,
+                    e
+                );
+            }
+        }
+    })
+}
+
+// Whether the file is the journal, or a file derived from a statement, when
+// they are written inside the watched directory.
+fn is_watch_output(file: &Path, canonical_journal_file: &Path) -> bool {
+    let is_derived = file.file_stem().is_some_and(|stem| {
+        let stem = stem.to_string_lossy();
+        stem.ends_with(REJECTS_FILE_SUFFIX) || stem.ends_with(UNMATCHED_RECORDS_FILE_SUFFIX)
+    });
+    is_derived
+        || file
+            .canonicalize()
+            .is_ok_and(|file| file == canonical_journal_file)
+}
+
+// Appends the entries of the file's records not already imported to the journal.
+fn watch_import_file(
+    importer: &FinancialImporter,
+    input_file: &Path,
+    policies: LoadPolicies,
+    journal_file: &Path,
+    imported: &mut ImportedEntries,
+) -> Result<()> {
+    let format_name = importer.format_for_file(None, input_file)?;
+    let output_relative_file = journal_file
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(input_file.file_name().unwrap());
+    let rejects_path =
+        get_derived_records_file_path(None, &output_relative_file, REJECTS_FILE_SUFFIX);
+    let ImportResult {
+        records_count,
+        rejected,
+        duplicates,
+        mut matched_entries,
+        mut fallback_entries,
+        errors,
+        ..
+    } = ImportSession::new(importer, &format_name).import(ImportSource::File {
+        path: input_file,
+        policies,
+        rejects_path: &rejects_path,
+    })?;
+    report_duplicates(&duplicates);
+
+    let mut entries = Vec::new();
+    entries.append(&mut matched_entries);
+    entries.append(&mut fallback_entries);
+    let entries_count = entries.len();
+
+    let mut new_entries = imported.retain_new(entries);
+    new_entries.sort();
+    append_ledger_entries_file(journal_file, &new_entries, &importer.formatting)?;
+
+    let timestamp = Local::now().format("%Y-%m-%d %H:%M:%S");
+    println!(
+        "{} Imported '{}' as '{}': {} records, {} new entries appended, {} already imported, {} rejected rows, {} failed records.",
+        timestamp,
+        input_file.file_name().unwrap().to_string_lossy(),
+        format_name,
+        records_count,
+        new_entries.len(),
+        entries_count - new_entries.len(),
+        rejected.len(),
+        errors.len()
+    );
+    for error in &errors {
+        eprintln!(
+            "{} Could not import a record of '{}': {:#}",
+            timestamp,
+            input_file.file_name().unwrap().to_string_lossy(),
+            error
+        );
+    }
+
+    Ok(())
+}
+
+// Moves the file into the archive directory, numbering it if the name is taken.
+fn archive_file(input_file: &Path, archive_dir: &Path) -> Result<()> {
+    let mut archive_path = archive_dir.join(input_file.file_name().unwrap());
+    let mut copy_number = 2;
+    while archive_path.exists() {
+        archive_path = get_derived_file_path(
+            None,
+            &archive_dir.join(input_file.file_name().unwrap()),
+            &format!("-{}", copy_number),
+        );
+        copy_number += 1;
+    }
+
+    trace!(
+        "Archiving '{}' to '{}'.",
+        input_file.to_str().unwrap(),
+        archive_path.to_str().unwrap()
+    );
+    std::fs::rename(input_file, &archive_path)?;
+
+    Ok(())
+}
+
+fn explain(importer: &FinancialImporter, format_name: &str, record: &SourceRecord) -> Result<()> {
+    let matcher = importer.matcher_for_format(format_name)?;
+    let MatchExplanation {
+        rule_matches,
+        rule_count,
+        fallback_match,
+        ledger_entry,
+    } = matcher.explain(&importer.accounts, importer.classifier.as_ref(), record)?;
+
+    println!(
+        "Explaining \"{}\" with the file format definition \"{}\":",
+        record.description, format_name
+    );
+
+    if let Some(fallback_match) = fallback_match {
+        println!(
+            "- None of the {} transaction rules matched, so the fallback rule is used:",
+            rule_count
+        );
+        print!("{}", describe_rule_match(&fallback_match));
+    } else {
+        println!(
+            "- {} of the {} transaction rules matched. Rules are tried in order and the first match is used:",
+            rule_matches.len(),
+            rule_count
+        );
+        for (position, rule_match) in rule_matches.iter().enumerate() {
+            println!(
+                "\n  {}. Rule {} of {}, {}",
+                position + 1,
+                rule_match.rule_index + 1,
+                rule_count,
+                if position == 0 {
+                    "USED as the first match."
+                } else {
+                    "not used, as an earlier rule matched."
+                }
+            );
+            print!("{}", describe_rule_match(rule_match));
+        }
+    }
+
+    println!("\nResulting Ledger entry:\n");
+    print!("{}", importer.formatting.format_entry(&ledger_entry));
+
+    Ok(())
+}
+
+fn describe_rule_match(rule_match: &RuleMatch) -> String {
+    let rule = rule_match.rule;
+    let mut description = format!(
+        "     - Name: {}\n     - Pattern: {}\n",
+        rule.name, rule.pattern_string
+    );
+
+    if !rule_match.captures.is_empty() {
+        let captures: Vec<String> = rule_match
+            .captures
+            .iter()
+            .map(|(group, value)| format!("${} = \"{}\"", group, value))
+            .collect();
+        writeln!(description, "     - Captures: {}", captures.join(", ")).unwrap();
+    }
+    if rule.payee_is_template {
+        writeln!(
+            description,
+            "     - Payee: template \"{}\" expands to \"{}\"",
+            rule.payee, rule_match.payee
+        )
+        .unwrap();
+    } else {
+        writeln!(description, "     - Payee: \"{}\"", rule_match.payee).unwrap();
+    }
+    writeln!(
+        description,
+        "     - Account 1: {} = {}\n     - Account 2: {} = {}",
+        rule.account1, rule_match.account1, rule.account2, rule_match.account2
+    )
+    .unwrap();
+
+    description
+}
+
+fn review(
+    importer: &FinancialImporter,
+    config_file: &Path,
+    format_name: &str,
+    input_file: &Path,
+    policies: LoadPolicies,
+    save_rules: bool,
+    ledger_output_file: &Path,
+) -> Result<()> {
+    let matcher = importer.matcher_for_format(format_name)?;
+    let records: Vec<SourceRecord> = load_input_records(input_file, policies)?;
+
+    let generated_entries = records
+        .iter()
+        .map(|record| importer.ledger_entry_for_source_record(format_name, record))
+        .collect::<Result<Vec<_>>>()?;
+    let (matched_entries, fallback_entries): (Vec<_>, Vec<_>) = generated_entries
+        .into_iter()
+        .partition(GeneratedLedgerEntry::is_from_matched_rule);
 
     let mut entries: Vec<LedgerEntry> = matched_entries
         .into_iter()
         .map(GeneratedLedgerEntry::unwrap_entry)
         .collect();
 
-    let matched_count = entries.len();
-    let unmatched_count = unmatched_entries.len();
+    let mut session = ReviewSession::new(&importer.accounts, matcher, &importer.formatting)?;
+    let ReviewOutcome {
+        entries: mut reviewed_entries,
+        new_rules,
+        reviewed_count,
+        unreviewed_count,
+    } = session.review(
+        fallback_entries
+            .into_iter()
+            .map(GeneratedLedgerEntry::unwrap)
+            .collect(),
+        save_rules,
+    )?;
 
-    let mut unmatched_entries = unmatched_entries;
-    entries.append(&mut unmatched_entries);
+    entries.append(&mut reviewed_entries);
     entries.sort();
 
     let entries_count = entries.len();
-    write_ledger_entries_file(ledger_output_file, entries)?;
+    write_ledger_entries_file(ledger_output_file, entries, &importer.formatting)?;
 
+    println!("\nSummary: ");
     println!(
         "- Wrote {} Ledger entries to file {}.",
         entries_count,
         ledger_output_file.to_str().unwrap()
     );
+    println!("   - {} fallback entries finalized.", reviewed_count);
     println!(
-        "   - {} Ledger entries generated from matching transaction rules.",
-        matched_count
+        "   - {} fallback entries left to finalize.",
+        unreviewed_count
+    );
+
+    if !new_rules.is_empty() {
+        let mut editor = ConfigurationEditor::load(config_file)?;
+        for rule in &new_rules {
+            editor.add_transaction_rule(format_name, rule)?;
+        }
+        let saved_paths = editor.save()?;
+
+        println!(
+            "- Saved {} new transaction rules to {}.",
+            new_rules.len(),
+            describe_paths(&saved_paths)
+        );
+    }
+
+    Ok(())
+}
+
+static SUGGESTION_EXAMPLE_COUNT: usize = 3;
+
+fn suggest_rules(
+    importer: &FinancialImporter,
+    format_name: &str,
+    input_file: &Path,
+    policies: LoadPolicies,
+    min_records: usize,
+    output_file: Option<PathBuf>,
+) -> Result<()> {
+    let matcher = importer.matcher_for_format(format_name)?;
+    let records: Vec<SourceRecord> = load_input_records(input_file, policies)?;
+    let suggestions = rule_generation::suggest_rules(&records, matcher, min_records)?;
+
+    let mut output = format!(
+        "# {} suggested rules for {} records from {}.\n# Replace each '{}' account alias before use.\n",
+        suggestions.len(),
+        records.len(),
+        input_file.to_str().unwrap(),
+        ACCOUNT_PLACEHOLDER
     );
+
+    for suggestion in &suggestions {
+        writeln!(
+            output,
+            "\n# Matches {} records, such as:",
+            suggestion.record_count
+        )?;
+        for description in suggestion
+            .descriptions
+            .iter()
+            .take(SUGGESTION_EXAMPLE_COUNT)
+        {
+            writeln!(output, "#   {}", description)?;
+        }
+        output.push_str(&transaction_rule_toml(format_name, &suggestion.rule)?);
+    }
+
+    match output_file {
+        Some(output_file) => std::fs::write(&output_file, output)?,
+        None => print!("{}", output),
+    }
+
+    Ok(())
+}
+
+fn learn_rules(
+    importer: &FinancialImporter,
+    format_name: &str,
+    journal_file: &Path,
+    options: &LearningOptions,
+    output_file: Option<PathBuf>,
+) -> Result<()> {
+    let journal = read_journal(journal_file, &importer.formatting)?;
+    let learned = rule_generation::learn_rules(&journal, &importer.accounts, options);
+
+    let mut output = format!(
+        "# {} rules learned from {} entries of {}.\n",
+        learned.rules.len(),
+        journal.entries.len(),
+        journal_file.to_str().unwrap()
+    );
+
+    if !learned.new_accounts.is_empty() {
+        writeln!(output, "\n# New account aliases:")?;
+        output.push_str(&accounts_toml(&learned.new_accounts)?);
+    }
+
+    for learned_rule in &learned.rules {
+        writeln!(
+            output,
+            "\n# Confidence {:.2}, from {} entries.",
+            learned_rule.confidence, learned_rule.occurrences
+        )?;
+        output.push_str(&transaction_rule_toml(format_name, &learned_rule.rule)?);
+    }
+
+    if !learned.conflicts.is_empty() {
+        writeln!(
+            output,
+            "\n# Conflicts, where the same description went to different accounts:"
+        )?;
+        for conflict in &learned.conflicts {
+            let accounts: Vec<String> = conflict
+                .accounts
+                .iter()
+                .map(|(account, count)| format!("{} ({})", account, count))
+                .collect();
+            writeln!(output, "#   {}: {}", conflict.key, accounts.join(", "))?;
+        }
+    }
+
+    match output_file {
+        Some(output_file) => std::fs::write(&output_file, output)?,
+        None => print!("{}", output),
+    }
+
+    Ok(())
+}
+
+fn train_classifier(
+    importer: &FinancialImporter,
+    config_file: &Path,
+    journal_file: &Path,
+    category_prefixes: &[String],
+) -> Result<()> {
+    let journal = read_journal(journal_file, &importer.formatting)?;
+    let classifier = CategoryClassifier::train(&journal, &importer.accounts, category_prefixes);
+    let model_path = classifier_model_path(config_file);
+    classifier.save(&model_path)?;
+
     println!(
-        "   - {} Ledger entries generated using the fallback rule.\n",
-        unmatched_count
+        "Trained the classifier on {} entries for {} account aliases, saved to {}.",
+        classifier.category_counts.values().sum::<usize>(),
+        classifier.category_counts.len(),
+        model_path.to_str().unwrap()
+    );
+
+    Ok(())
+}
+
+fn reconcile(input_file: &Path, opening_balance: f64, closing_balance: f64) -> Result<()> {
+    let reconciliation =
+        StatementReconciliation::for_file(input_file, opening_balance, closing_balance)?;
+
+    println!("Reconciliation: ");
+    println!(
+        "- Loaded {} source records from file {}.",
+        reconciliation.records.len(),
+        input_file.to_str().unwrap()
+    );
+    println!("- Opening balance:          {:>12.2}", opening_balance);
+    println!(
+        "- Total of source records:  {:>12.2}",
+        reconciliation.records_total()
     );
     println!(
-        "- Wrote {} unmatched source records to the file {}.",
-        unmatched_count,
-        unmatched_records_path.to_str().unwrap()
+        "- Computed closing balance: {:>12.2}",
+        reconciliation.computed_closing_balance()
     );
+    println!("- Statement closing balance:{:>12.2}\n", closing_balance);
 
-    if errors.is_empty() {
-        Ok(())
-    } else {
-        Map::fold(
-            errors.into_iter().map(Result::unwrap_err),
-            Err(eyre!("One or more errors were reported!")),
-            |report, e| report.section(e),
-        )
+    if reconciliation.reconciles() {
+        println!("The statement reconciles.");
+        return Ok(());
     }
+
+    println!(
+        "The statement does NOT reconcile, off by {:.2}.",
+        reconciliation.difference()
+    );
+
+    let duplicates = reconciliation.duplicate_records();
+    if !duplicates.is_empty() {
+        println!("\nPossible duplicate records:");
+        for (original, duplicate) in &duplicates {
+            println!(
+                "- {} (duplicates {})",
+                describe_source_record(duplicate),
+                describe_line_number(original.line_number)
+            );
+        }
+    }
+
+    if !reconciliation.rejected.is_empty() {
+        println!("\nRecords that failed to load:");
+        for rejected in &reconciliation.rejected {
+            println!("- {}", rejected);
+        }
+    }
+
+    Err(eyre!(
+        "Statement does not reconcile, difference of {:.2}.",
+        reconciliation.difference()
+    ))
+}
+
+fn describe_source_record(record: &SourceRecord) -> String {
+    format!(
+        "{}: {} {} {:.2}",
+        describe_line_number(record.line_number),
+        record.date,
+        record.description,
+        record.amount
+    )
+}
+
+fn describe_paths(paths: &[&Path]) -> String {
+    paths
+        .iter()
+        .map(|path| path.to_str().unwrap())
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
 static UNMATCHED_RECORDS_FILE_SUFFIX: &str = "-unmatched";
+static REJECTS_FILE_SUFFIX: &str = "-rejects";
+
+// Loads the records of an input file for the commands that only read it, with
+// quarantined rows written next to it.
+fn load_input_records(input_file: &Path, policies: LoadPolicies) -> Result<Vec<SourceRecord>> {
+    let rejects_path = get_derived_records_file_path(None, input_file, REJECTS_FILE_SUFFIX);
+    let loaded = source_record::load_source_records(input_file, policies, &rejects_path)?;
+    report_duplicates(&loaded.duplicates);
+    Ok(loaded.records)
+}
+
+fn report_duplicates(duplicates: &[SuspectedDuplicate]) {
+    for duplicate in duplicates {
+        eprintln!("Suspected duplicate: {}", duplicate);
+    }
+}
+
+// Like `get_derived_file_path`, for the CSV files of unmatched and rejected
+// records, which are named `.csv` whatever the input file, such as an OFX statement.
+fn get_derived_records_file_path(
+    file: Option<PathBuf>,
+    input_file: &Path,
+    suffix: &str,
+) -> PathBuf {
+    file.unwrap_or_else(|| get_derived_file_path(None, input_file, suffix).with_extension("csv"))
+}
 
-fn get_unmatched_file_path(unmatched_records_file: Option<PathBuf>, input_file: &Path) -> PathBuf {
-    unmatched_records_file.map_or_else(
+// Returns the given file, or one next to the input file with the suffix added to its name.
+#[allow(clippy::unnecessary_option_map_or_else)]
+fn get_derived_file_path(file: Option<PathBuf>, input_file: &Path, suffix: &str) -> PathBuf {
+    file.map_or_else(
         || {
-            let mut unmatched_records_path = PathBuf::new();
-            unmatched_records_path.push(input_file.parent().unwrap());
+            let mut derived_path = PathBuf::new();
+            derived_path.push(input_file.parent().unwrap());
             let mut filename = input_file.file_stem().unwrap().to_os_string();
-            filename.push(UNMATCHED_RECORDS_FILE_SUFFIX);
-            unmatched_records_path.push(filename);
-            unmatched_records_path.set_extension(input_file.extension().unwrap());
-            unmatched_records_path
+            filename.push(suffix);
+            derived_path.push(filename);
+            if let Some(extension) = input_file.extension() {
+                derived_path.set_extension(extension);
+            }
+            derived_path
         },
-        |unmatched_records_file| unmatched_records_file,
+        |file| file,
     )
 }
 
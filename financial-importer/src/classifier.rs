@@ -0,0 +1,184 @@
+use crate::ledger_entry::Journal;
+use crate::rule_generation::description_tokens;
+use crate::rule_generation::learn::{categorized_entry, CategorizedEntry};
+use crate::transaction_matcher::definitions::{alias_for_account, AccountMap};
+use color_eyre::eyre::{Result, WrapErr};
+use log::{info, trace, warn};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+pub static CLASSIFIER_MODEL_FILE_NAME: &str = "classifier-model.toml";
+static WORD_FEATURE_PREFIX: &str = "word:";
+static AMOUNT_FEATURE_PREFIX: &str = "amount:";
+
+// A naive Bayes model from the words of a description, and the size of its
+// amount, to the alias of the account the record belongs in.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CategoryClassifier {
+    // The number of training entries for each account alias.
+    pub category_counts: BTreeMap<String, usize>,
+    // For each account alias, the number of times each feature was seen.
+    pub feature_counts: BTreeMap<String, BTreeMap<String, usize>>,
+}
+
+pub struct CategoryPrediction {
+    pub account_alias: String,
+    // The probability of the prediction relative to the other accounts, from 0 to 1.
+    pub score: f64,
+}
+
+// The model is kept in the same directory as the configuration file.
+#[must_use]
+pub fn classifier_model_path(config_file: &Path) -> PathBuf {
+    config_file
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(CLASSIFIER_MODEL_FILE_NAME)
+}
+
+impl CategoryClassifier {
+    // Trains on the journal entries with a single category posting to an
+    // account that has an alias, directly or through a wildcard alias,
+    // skipping all others.
+    #[must_use]
+    pub fn train(
+        journal: &Journal,
+        accounts: &AccountMap,
+        category_account_prefixes: &[String],
+    ) -> Self {
+        let mut classifier = CategoryClassifier::default();
+        let mut skipped = 0;
+
+        for entry in &journal.entries {
+            let Some(CategorizedEntry {
+                description,
+                category_account,
+                category_amount,
+                ..
+            }) = categorized_entry(entry, category_account_prefixes)
+            else {
+                skipped += 1;
+                continue;
+            };
+            let Some(alias) = alias_for_account(accounts, category_account) else {
+                skipped += 1;
+                continue;
+            };
+
+            *classifier.category_counts.entry(alias.clone()).or_default() += 1;
+
+            let feature_counts = classifier.feature_counts.entry(alias).or_default();
+            for feature in features(description, category_amount) {
+                *feature_counts.entry(feature).or_default() += 1;
+            }
+        }
+
+        info!(
+            "Trained classifier on {} entries for {} accounts, skipping {} entries.",
+            journal.entries.len() - skipped,
+            classifier.category_counts.len(),
+            skipped
+        );
+
+        classifier
+    }
+
+    pub fn load(model_path: &Path) -> Result<Self> {
+        trace!(
+            "Loading classifier model from '{}'.",
+            model_path.to_str().unwrap()
+        );
+
+        let contents = std::fs::read_to_string(model_path).wrap_err_with(|| {
+            format!(
+                "Encountered errors reading classifier model '{}'.",
+                model_path.to_str().unwrap()
+            )
+        })?;
+
+        Ok(toml::from_str(&contents)?)
+    }
+
+    pub fn save(&self, model_path: &Path) -> Result<()> {
+        std::fs::write(model_path, toml::to_string(self)?).wrap_err_with(|| {
+            format!(
+                "Encountered errors writing classifier model '{}'.",
+                model_path.to_str().unwrap()
+            )
+        })
+    }
+
+    #[must_use]
+    pub fn predict(&self, description: &str, amount: f64) -> Option<CategoryPrediction> {
+        let total_count: usize = self.category_counts.values().sum();
+        if total_count == 0 {
+            return None;
+        }
+
+        let record_features = features(description, amount);
+        let vocabulary_size = self
+            .feature_counts
+            .values()
+            .flat_map(BTreeMap::keys)
+            .collect::<BTreeSet<_>>()
+            .len() as f64;
+
+        // Log probabilities, with add-one smoothing for features unseen for an
+        // account. Accounts without feature counts, as in an edited model, are skipped.
+        let log_probabilities: Vec<(&String, f64)> = self
+            .category_counts
+            .iter()
+            .filter_map(|(alias, count)| {
+                let Some(feature_counts) = self.feature_counts.get(alias) else {
+                    warn!(
+                        "Skipping account alias '{}' without feature counts in the classifier model.",
+                        alias
+                    );
+                    return None;
+                };
+                let feature_total = feature_counts.values().sum::<usize>() as f64;
+                let log_prior = (*count as f64 / total_count as f64).ln();
+                let log_likelihood: f64 = record_features
+                    .iter()
+                    .map(|feature| {
+                        let feature_count = feature_counts.get(feature).copied().unwrap_or(0);
+                        ((feature_count as f64 + 1.0) / (feature_total + vocabulary_size)).ln()
+                    })
+                    .sum();
+                Some((alias, log_prior + log_likelihood))
+            })
+            .collect();
+
+        let (best_alias, best_log_probability) = log_probabilities
+            .iter()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+        // Normalize against the other accounts to turn the best into a score
+        let normalizer: f64 = log_probabilities
+            .iter()
+            .map(|(_, log_probability)| (log_probability - best_log_probability).exp())
+            .sum();
+
+        Some(CategoryPrediction {
+            account_alias: String::from(*best_alias),
+            score: 1.0 / normalizer,
+        })
+    }
+}
+
+// The words of the description, plus the order of magnitude of the amount.
+fn features(description: &str, amount: f64) -> Vec<String> {
+    let mut features: Vec<String> = description_tokens(description)
+        .into_iter()
+        .map(|token| format!("{}{}", WORD_FEATURE_PREFIX, token))
+        .collect();
+
+    let magnitude = if amount.abs() < 1.0 {
+        0
+    } else {
+        amount.abs().log10().floor() as i32 + 1
+    };
+    features.push(format!("{}{}", AMOUNT_FEATURE_PREFIX, magnitude));
+
+    features
+}
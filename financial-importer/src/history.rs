@@ -0,0 +1,355 @@
+use crate::app::{APP_NAME, DEFAULT_HISTORY_FILE_NAME};
+use crate::source_record::SourceRecord;
+use chrono::{Local, NaiveDate, NaiveDateTime};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use log::trace;
+use platform_dirs::AppDirs;
+use serde::{Deserialize, Serialize};
+use std::borrow::Borrow;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+// One imported source record, as kept in the history file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub batch_id: u64,
+    pub imported_at: NaiveDateTime,
+    pub format_name: String,
+    pub source_file: String,
+    pub output_file: String,
+    pub date: NaiveDate,
+    pub amount: f64,
+    // Upper cased, with runs of whitespace collapsed.
+    pub description: String,
+    pub fitid: Option<String>,
+}
+
+impl HistoryRecord {
+    // The source record as far as the history keeps it, with its description normalized.
+    #[must_use]
+    pub fn source_record(&self) -> SourceRecord {
+        SourceRecord {
+            date: self.date,
+            description: self.description.clone(),
+            amount: self.amount,
+            original_amount: None,
+            original_currency: None,
+            fitid: self.fitid.clone(),
+            line_number: None,
+        }
+    }
+}
+
+// What identifies a record as imported before: the statement's own ID when it
+// has one, and otherwise its date, amount and normalized description.
+#[derive(PartialEq, Eq, Hash)]
+enum RecordFingerprint {
+    ById {
+        format_name: String,
+        fitid: String,
+    },
+    ByContents {
+        format_name: String,
+        date: NaiveDate,
+        hundredths: i64,
+        description: String,
+    },
+}
+
+impl RecordFingerprint {
+    fn new(
+        format_name: &str,
+        date: NaiveDate,
+        amount: f64,
+        description: &str,
+        fitid: Option<&str>,
+    ) -> Self {
+        match fitid {
+            Some(fitid) if !fitid.is_empty() => RecordFingerprint::ById {
+                format_name: String::from(format_name),
+                fitid: String::from(fitid),
+            },
+            _ => RecordFingerprint::ByContents {
+                format_name: String::from(format_name),
+                date,
+                hundredths: (amount * 100.0).round() as i64,
+                description: normalize_description(description),
+            },
+        }
+    }
+
+    fn for_source_record(format_name: &str, record: &SourceRecord) -> Self {
+        RecordFingerprint::new(
+            format_name,
+            record.date,
+            record.amount,
+            &record.description,
+            record.fitid.as_deref(),
+        )
+    }
+
+    fn for_history_record(record: &HistoryRecord) -> Self {
+        RecordFingerprint::new(
+            &record.format_name,
+            record.date,
+            record.amount,
+            &record.description,
+            record.fitid.as_deref(),
+        )
+    }
+}
+
+fn normalize_description(description: &str) -> String {
+    description
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_uppercase()
+}
+
+// The records imported in one run.
+pub struct ImportBatch {
+    pub batch_id: u64,
+    pub imported_at: NaiveDateTime,
+    pub format_name: String,
+    pub source_file: String,
+    pub output_file: String,
+    pub record_count: usize,
+}
+
+// Every source record imported before, grouped into batches by run, kept in
+// a CSV file so that later runs can skip the records already imported.
+pub struct ImportHistory {
+    path: PathBuf,
+    records: Vec<HistoryRecord>,
+}
+
+// The given history file, or the default one in the platform's data directory.
+pub fn history_file_path(history_file: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(history_file) = history_file {
+        return Ok(history_file);
+    }
+
+    let app_dirs = AppDirs::new(Some(APP_NAME), false)
+        .ok_or_else(|| eyre!("Problems getting app data directory path"))?;
+    Ok(app_dirs.data_dir.join(DEFAULT_HISTORY_FILE_NAME))
+}
+
+impl ImportHistory {
+    // An empty history when the file doesn't exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut records = Vec::new();
+        if path.exists() {
+            let mut reader = csv::Reader::from_path(path).wrap_err_with(|| {
+                format!(
+                    "Encountered errors reading import history '{}'.",
+                    path.to_str().unwrap()
+                )
+            })?;
+            for record in reader.deserialize() {
+                records.push(record.wrap_err_with(|| {
+                    format!(
+                        "Encountered errors reading import history '{}'.",
+                        path.to_str().unwrap()
+                    )
+                })?);
+            }
+        }
+
+        trace!(
+            "Loaded {} imported records from history '{}'.",
+            records.len(),
+            path.to_str().unwrap()
+        );
+
+        Ok(ImportHistory {
+            path: path.to_path_buf(),
+            records,
+        })
+    }
+
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // Whether each record was imported before. As many of the records with
+    // the same fingerprint as were imported before count as seen, so that
+    // records legitimately repeated in a statement are still imported.
+    #[must_use]
+    pub fn seen_records<R: Borrow<SourceRecord>>(
+        &self,
+        format_name: &str,
+        records: &[R],
+    ) -> Vec<bool> {
+        let mut imported_counts: HashMap<RecordFingerprint, usize> = HashMap::new();
+        for record in &self.records {
+            *imported_counts
+                .entry(RecordFingerprint::for_history_record(record))
+                .or_default() += 1;
+        }
+
+        records
+            .iter()
+            .map(|record| {
+                match imported_counts.get_mut(&RecordFingerprint::for_source_record(
+                    format_name,
+                    record.borrow(),
+                )) {
+                    Some(count) if *count > 0 => {
+                        *count -= 1;
+                        true
+                    }
+                    _ => false,
+                }
+            })
+            .collect()
+    }
+
+    // Adds the records not already in the history to the history file as a new
+    // batch, returning it, or nothing if all of them were.
+    pub fn record_batch(
+        &mut self,
+        format_name: &str,
+        source_file: &Path,
+        output_file: &Path,
+        records: &[&SourceRecord],
+    ) -> Result<Option<ImportBatch>> {
+        let seen = self.seen_records(format_name, records);
+        let records: Vec<&SourceRecord> = records
+            .iter()
+            .zip(seen)
+            .filter(|(_, seen)| !seen)
+            .map(|(record, _)| *record)
+            .collect();
+        if records.is_empty() {
+            trace!("All the records are in the history already, recording no batch.");
+            return Ok(None);
+        }
+
+        let batch_id = self
+            .records
+            .iter()
+            .map(|record| record.batch_id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        let imported_at = Local::now().naive_local();
+
+        let batch_records: Vec<HistoryRecord> = records
+            .iter()
+            .map(|record| HistoryRecord {
+                batch_id,
+                imported_at,
+                format_name: String::from(format_name),
+                source_file: source_file.to_string_lossy().into_owned(),
+                output_file: output_file.to_string_lossy().into_owned(),
+                date: record.date,
+                amount: record.amount,
+                description: normalize_description(&record.description),
+                fitid: record.fitid.clone(),
+            })
+            .collect();
+
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let is_new_file =
+            std::fs::metadata(&self.path).map_or(true, |metadata| metadata.len() == 0);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(is_new_file)
+            .from_writer(file);
+        for record in &batch_records {
+            writer.serialize(record)?;
+        }
+        writer.flush()?;
+
+        trace!(
+            "Recorded batch {} of {} records in history '{}'.",
+            batch_id,
+            batch_records.len(),
+            self.path.to_str().unwrap()
+        );
+
+        let batch = ImportBatch {
+            batch_id,
+            imported_at,
+            format_name: String::from(format_name),
+            source_file: source_file.to_string_lossy().into_owned(),
+            output_file: output_file.to_string_lossy().into_owned(),
+            record_count: batch_records.len(),
+        };
+        self.records.extend(batch_records);
+
+        Ok(Some(batch))
+    }
+
+    #[must_use]
+    pub fn records(&self) -> &[HistoryRecord] {
+        &self.records
+    }
+
+    #[must_use]
+    pub fn batches(&self) -> Vec<ImportBatch> {
+        let mut batches: BTreeMap<u64, ImportBatch> = BTreeMap::new();
+        for record in &self.records {
+            batches
+                .entry(record.batch_id)
+                .or_insert_with(|| ImportBatch {
+                    batch_id: record.batch_id,
+                    imported_at: record.imported_at,
+                    format_name: record.format_name.clone(),
+                    source_file: record.source_file.clone(),
+                    output_file: record.output_file.clone(),
+                    record_count: 0,
+                })
+                .record_count += 1;
+        }
+
+        batches.into_values().collect()
+    }
+
+    #[must_use]
+    pub fn batch_records(&self, batch_id: u64) -> Vec<&HistoryRecord> {
+        self.records
+            .iter()
+            .filter(|record| record.batch_id == batch_id)
+            .collect()
+    }
+
+    // Forgets the records of the batch, so that they are imported again by
+    // later runs, and rewrites the history file. Returns the removed records.
+    pub fn undo_batch(&mut self, batch_id: u64) -> Result<Vec<HistoryRecord>> {
+        let (removed, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.records)
+            .into_iter()
+            .partition(|record| record.batch_id == batch_id);
+        self.records = kept;
+
+        if removed.is_empty() {
+            return Err(eyre!(
+                "No import batch with ID {} in the history.",
+                batch_id
+            ));
+        }
+
+        let mut writer = csv::Writer::from_path(&self.path)?;
+        for record in &self.records {
+            writer.serialize(record)?;
+        }
+        writer.flush()?;
+
+        trace!(
+            "Removed {} records of batch {} from history '{}'.",
+            removed.len(),
+            batch_id,
+            self.path.to_str().unwrap()
+        );
+
+        Ok(removed)
+    }
+}
@@ -0,0 +1,241 @@
+use crate::history::ImportHistory;
+use crate::ledger_entry::{write_ledger_entries_file, LedgerEntry, LedgerFormat};
+use crate::source_record::{
+    load_source_records, write_source_records, LoadPolicies, LoadedSourceRecords,
+    RejectedSourceRecord, SourceRecord, SuspectedDuplicate,
+};
+use crate::transaction_matcher::{FinancialImporter, GeneratedLedgerEntry};
+use chrono::NaiveDate;
+use color_eyre::eyre::Result;
+use log::trace;
+use serde::Serialize;
+use std::path::Path;
+
+// Where the records of an import come from.
+pub enum ImportSource<'a> {
+    // A CSV file, loaded with the policies. Quarantined rows are written to the rejects file.
+    File {
+        path: &'a Path,
+        policies: LoadPolicies,
+        rejects_path: &'a Path,
+    },
+    // Records the caller has already loaded.
+    Records(LoadedSourceRecords),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RecordOutcome {
+    Matched,
+    Fallback,
+    Error,
+    AlreadyImported,
+}
+
+// What became of one record of the source.
+#[derive(Debug, Serialize)]
+pub struct RecordResult {
+    pub line_number: Option<u64>,
+    pub date: NaiveDate,
+    pub description: String,
+    pub amount: f64,
+    pub outcome: RecordOutcome,
+    // The rule that generated the record's entry, and the entry's payee.
+    pub rule_name: Option<String>,
+    pub payee: Option<String>,
+    pub error: Option<String>,
+}
+
+impl RecordResult {
+    fn new(record: &SourceRecord, outcome: RecordOutcome) -> Self {
+        RecordResult {
+            line_number: record.line_number,
+            date: record.date,
+            description: record.description.clone(),
+            amount: record.amount,
+            outcome,
+            rule_name: None,
+            payee: None,
+            error: None,
+        }
+    }
+
+    fn for_result(record: &SourceRecord, result: &Result<GeneratedLedgerEntry>) -> Self {
+        match result {
+            Ok(generated) => {
+                let outcome = if generated.is_from_matched_rule() {
+                    RecordOutcome::Matched
+                } else {
+                    RecordOutcome::Fallback
+                };
+                let payee = match generated {
+                    GeneratedLedgerEntry::ByMatchedRule { ledger_entry, .. }
+                    | GeneratedLedgerEntry::ByFallback { ledger_entry, .. } => {
+                        ledger_entry.payee.clone()
+                    }
+                };
+                RecordResult {
+                    rule_name: Some(String::from(generated.rule_name())),
+                    payee: Some(payee),
+                    ..RecordResult::new(record, outcome)
+                }
+            }
+            Err(error) => RecordResult {
+                error: Some(format!("{:#}", error)),
+                ..RecordResult::new(record, RecordOutcome::Error)
+            },
+        }
+    }
+}
+
+pub struct ImportResult {
+    // How many records were loaded, including those already imported.
+    pub records_count: usize,
+    pub rejected: Vec<RejectedSourceRecord>,
+    // The records suspected to be duplicates of earlier ones.
+    pub duplicates: Vec<SuspectedDuplicate>,
+    // Records skipped as the history shows they were imported before.
+    pub already_imported_count: usize,
+    pub matched_entries: Vec<LedgerEntry>,
+    pub fallback_entries: Vec<LedgerEntry>,
+    // The records the fallback entries were generated from, sorted.
+    pub unmatched_records: Vec<SourceRecord>,
+    // The records the entries were generated from, in the order of the source.
+    pub imported_records: Vec<SourceRecord>,
+    // What became of each record, in the order of the source.
+    pub record_results: Vec<RecordResult>,
+    pub errors: Vec<color_eyre::Report>,
+}
+
+impl ImportResult {
+    // The matched and fallback entries together, sorted.
+    #[must_use]
+    pub fn entries(&self) -> Vec<&LedgerEntry> {
+        let mut entries: Vec<&LedgerEntry> = self
+            .matched_entries
+            .iter()
+            .chain(&self.fallback_entries)
+            .collect();
+        entries.sort();
+        entries
+    }
+
+    pub fn write_ledger_entries(&self, path: &Path, format: &LedgerFormat) -> Result<()> {
+        write_ledger_entries_file(path, self.entries(), format)
+    }
+
+    pub fn write_unmatched_records(&self, path: &Path) -> Result<()> {
+        write_source_records(path, &self.unmatched_records.iter().collect::<Vec<_>>())
+    }
+}
+
+// Turns the records of a source into Ledger entries with the rules of one
+// format, leaving writing the results to the caller.
+pub struct ImportSession<'a> {
+    importer: &'a FinancialImporter,
+    format_name: &'a str,
+    history: Option<&'a ImportHistory>,
+}
+
+impl<'a> ImportSession<'a> {
+    #[must_use]
+    pub fn new(importer: &'a FinancialImporter, format_name: &'a str) -> Self {
+        Self {
+            importer,
+            format_name,
+            history: None,
+        }
+    }
+
+    // Skips the records the history shows were imported before.
+    #[must_use]
+    pub fn with_history(self, history: &'a ImportHistory) -> Self {
+        Self {
+            history: Some(history),
+            ..self
+        }
+    }
+
+    pub fn import(&self, source: ImportSource) -> Result<ImportResult> {
+        let LoadedSourceRecords {
+            records,
+            rejected,
+            duplicates,
+            ..
+        } = match source {
+            ImportSource::File {
+                path,
+                policies,
+                rejects_path,
+            } => load_source_records(path, policies, rejects_path)?,
+            ImportSource::Records(loaded) => loaded,
+        };
+        let records_count = records.len();
+        trace!(
+            "Importing {} source records with the format '{}'.",
+            records_count,
+            self.format_name
+        );
+
+        let seen = match self.history {
+            Some(history) => history.seen_records(self.format_name, &records),
+            None => vec![false; records_count],
+        };
+        let mut record_results = Vec::new();
+        let records: Vec<SourceRecord> = records
+            .into_iter()
+            .zip(seen)
+            .filter_map(|(record, seen)| {
+                if seen {
+                    record_results.push(RecordResult::new(&record, RecordOutcome::AlreadyImported));
+                    None
+                } else {
+                    Some(record)
+                }
+            })
+            .collect();
+        let already_imported_count = records_count - records.len();
+
+        let mut matched_entries = Vec::new();
+        let mut fallback_entries = Vec::new();
+        let mut unmatched_records = Vec::new();
+        let mut imported_records = Vec::new();
+        let mut errors = Vec::new();
+
+        for record in &records {
+            let result = self
+                .importer
+                .ledger_entry_for_source_record(self.format_name, record);
+            record_results.push(RecordResult::for_result(record, &result));
+
+            match result {
+                Ok(GeneratedLedgerEntry::ByMatchedRule { ledger_entry, .. }) => {
+                    matched_entries.push(ledger_entry);
+                    imported_records.push(record.clone());
+                }
+                Ok(GeneratedLedgerEntry::ByFallback { ledger_entry, .. }) => {
+                    fallback_entries.push(ledger_entry);
+                    unmatched_records.push(record.clone());
+                    imported_records.push(record.clone());
+                }
+                Err(error) => errors.push(error),
+            }
+        }
+
+        unmatched_records.sort();
+        record_results.sort_by_key(|result| result.line_number);
+
+        Ok(ImportResult {
+            records_count,
+            rejected,
+            duplicates,
+            already_imported_count,
+            matched_entries,
+            fallback_entries,
+            unmatched_records,
+            imported_records,
+            record_results,
+            errors,
+        })
+    }
+}
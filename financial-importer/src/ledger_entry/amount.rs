@@ -0,0 +1,167 @@
+use super::format::DEFAULT_LEDGER_FORMAT;
+use serde::Deserialize;
+use std::fmt;
+
+static DEFAULT_COMMODITY_SYMBOL: &str = "$";
+static DEFAULT_COMMODITY_PRECISION: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CommodityPosition {
+    // Written before the quantity, like `$10.00`.
+    Prefix,
+    // Written after the quantity, like `10.00 EUR`.
+    Suffix,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "CommodityConfiguration")]
+pub struct Commodity {
+    pub symbol: String,
+    pub position: CommodityPosition,
+    pub precision: usize,
+}
+
+impl Commodity {
+    #[must_use]
+    pub fn new(symbol: &str) -> Self {
+        // Currency signs like `$` or `€` go before the amount, names like `EUR` or `AAPL` after.
+        let position = if symbol.chars().all(|c| !c.is_alphanumeric()) {
+            CommodityPosition::Prefix
+        } else {
+            CommodityPosition::Suffix
+        };
+
+        Commodity {
+            symbol: String::from(symbol),
+            position,
+            precision: DEFAULT_COMMODITY_PRECISION,
+        }
+    }
+
+    // Ledger requires commodities containing digits, whitespace or operators to be quoted.
+    fn needs_quotes(&self) -> bool {
+        self.symbol
+            .chars()
+            .any(|c| c.is_numeric() || c.is_whitespace() || "-+*/^&|=<>{}[]()@;.,\"".contains(c))
+    }
+
+    // Half of the smallest displayed unit, within which a total is considered zero.
+    #[must_use]
+    pub fn tolerance(&self) -> f64 {
+        0.5 * 10_f64.powi(-(self.precision as i32))
+    }
+}
+
+impl Default for Commodity {
+    fn default() -> Self {
+        Commodity::new(DEFAULT_COMMODITY_SYMBOL)
+    }
+}
+
+impl fmt::Display for Commodity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.needs_quotes() {
+            write!(f, "\"{}\"", self.symbol)
+        } else {
+            write!(f, "{}", self.symbol)
+        }
+    }
+}
+
+// A commodity may be configured by its symbol alone, or with its position and precision.
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum CommodityConfiguration {
+    Symbol(String),
+    Detailed {
+        symbol: String,
+        position: Option<CommodityPosition>,
+        precision: Option<usize>,
+    },
+}
+
+impl From<CommodityConfiguration> for Commodity {
+    fn from(configuration: CommodityConfiguration) -> Self {
+        match configuration {
+            CommodityConfiguration::Symbol(symbol) => Commodity::new(&symbol),
+            CommodityConfiguration::Detailed {
+                symbol,
+                position,
+                precision,
+            } => {
+                let mut commodity = Commodity::new(&symbol);
+                if let Some(position) = position {
+                    commodity.position = position;
+                }
+                if let Some(precision) = precision {
+                    commodity.precision = precision;
+                }
+                commodity
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Amount {
+    pub quantity: f64,
+    pub commodity: Commodity,
+}
+
+impl Amount {
+    #[must_use]
+    pub fn new(quantity: f64, commodity: Commodity) -> Self {
+        Amount {
+            quantity,
+            commodity,
+        }
+    }
+}
+
+impl std::ops::Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Self::Output {
+        Amount::new(-self.quantity, self.commodity)
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", DEFAULT_LEDGER_FORMAT.format_amount(self))
+    }
+}
+
+// The cost or price attached to a posting, which is what the posting balances against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PostingPrice {
+    // `@`: the price of a single unit.
+    PerUnit(Amount),
+    // `@@`: the price of the whole quantity.
+    Total(Amount),
+    // `{}`: the per-unit cost basis of a lot.
+    LotCost(Amount),
+}
+
+impl PostingPrice {
+    // The amount that the posting contributes to the balance of its entry.
+    #[must_use]
+    pub fn cost_of(&self, amount: &Amount) -> Amount {
+        match self {
+            PostingPrice::PerUnit(price) | PostingPrice::LotCost(price) => {
+                Amount::new(amount.quantity * price.quantity, price.commodity.clone())
+            }
+            PostingPrice::Total(price) => Amount::new(
+                price.quantity.abs().copysign(amount.quantity),
+                price.commodity.clone(),
+            ),
+        }
+    }
+}
+
+impl fmt::Display for PostingPrice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", DEFAULT_LEDGER_FORMAT.format_price(self))
+    }
+}
@@ -0,0 +1,171 @@
+use super::amount::{Amount, CommodityPosition, PostingPrice};
+use super::{EntryLine, LedgerEntry};
+use lazy_static::lazy_static;
+use serde::Deserialize;
+use std::fmt::Write;
+
+static DEFAULT_INDENT: usize = 4;
+static DEFAULT_ALIGNMENT_COLUMN: usize = 67;
+static DEFAULT_THOUSANDS_SEPARATOR: &str = ",";
+static DEFAULT_DECIMAL_MARK: char = '.';
+// Ledger needs at least two spaces to tell where an account name ends.
+static MINIMUM_ACCOUNT_SEPARATION: usize = 2;
+
+// Where the minus sign of a negative amount goes relative to a prefix commodity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NegativeStyle {
+    // `$-10.00`, as written by `ledger print`.
+    AfterCommodity,
+    // `-$10.00`, as written by `hledger print`.
+    BeforeCommodity,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LedgerFormat {
+    // Number of spaces before each posting and comment line.
+    pub indent: usize,
+    // The column, counted from zero, that amounts end at when the account name leaves room.
+    pub alignment_column: usize,
+    pub thousands_separator: String,
+    pub decimal_mark: char,
+    pub negative_style: NegativeStyle,
+}
+
+lazy_static! {
+    // Used by the `Display` implementations of entries and amounts.
+    pub(super) static ref DEFAULT_LEDGER_FORMAT: LedgerFormat = LedgerFormat::default();
+}
+
+impl Default for LedgerFormat {
+    fn default() -> Self {
+        LedgerFormat {
+            indent: DEFAULT_INDENT,
+            alignment_column: DEFAULT_ALIGNMENT_COLUMN,
+            thousands_separator: String::from(DEFAULT_THOUSANDS_SEPARATOR),
+            decimal_mark: DEFAULT_DECIMAL_MARK,
+            negative_style: NegativeStyle::AfterCommodity,
+        }
+    }
+}
+
+impl LedgerFormat {
+    #[must_use]
+    pub fn format_quantity(&self, quantity: f64, precision: usize) -> String {
+        let digits = format!("{:.*}", precision, quantity.abs());
+        let (integer_part, fraction_part) = digits.split_once('.').unwrap_or((&digits, ""));
+
+        let mut formatted = String::new();
+        // Avoid writing `-0.00` for amounts that round to zero.
+        if quantity < 0.0 && digits.chars().any(|c| ('1'..='9').contains(&c)) {
+            formatted.push('-');
+        }
+
+        for (index, digit) in integer_part.chars().enumerate() {
+            if index > 0 && (integer_part.len() - index) % 3 == 0 {
+                formatted.push_str(&self.thousands_separator);
+            }
+            formatted.push(digit);
+        }
+
+        if !fraction_part.is_empty() {
+            formatted.push(self.decimal_mark);
+            formatted.push_str(fraction_part);
+        }
+
+        formatted
+    }
+
+    #[must_use]
+    pub fn format_amount(&self, amount: &Amount) -> String {
+        let commodity = &amount.commodity;
+        let formatted_number = self.format_quantity(amount.quantity, commodity.precision);
+
+        match commodity.position {
+            CommodityPosition::Suffix => format!("{} {}", formatted_number, commodity),
+            CommodityPosition::Prefix => {
+                let separator = if commodity.symbol.chars().all(char::is_alphabetic) {
+                    " "
+                } else {
+                    ""
+                };
+
+                match formatted_number.strip_prefix('-') {
+                    Some(unsigned_number)
+                        if self.negative_style == NegativeStyle::BeforeCommodity =>
+                    {
+                        format!("-{}{}{}", commodity, separator, unsigned_number)
+                    }
+                    _ => format!("{}{}{}", commodity, separator, formatted_number),
+                }
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn format_price(&self, price: &PostingPrice) -> String {
+        match price {
+            PostingPrice::PerUnit(price) => format!("@ {}", self.format_amount(price)),
+            PostingPrice::Total(price) => format!("@@ {}", self.format_amount(price)),
+            PostingPrice::LotCost(price) => format!("{{{}}}", self.format_amount(price)),
+        }
+    }
+
+    #[must_use]
+    pub fn format_entry_line(&self, line: &EntryLine) -> String {
+        let indent = " ".repeat(self.indent);
+
+        match line {
+            EntryLine::Comment { comment } => format!("{}; {}", indent, comment),
+            EntryLine::Posting {
+                account,
+                amount,
+                price,
+            } => {
+                let formatted_amount = self.format_amount(amount);
+                // Right align the amount at the alignment column, unless the account
+                // name is too long, in which case the amount simply follows it.
+                let used_width =
+                    self.indent + account.chars().count() + formatted_amount.chars().count();
+                let separation = (self.alignment_column + 1)
+                    .saturating_sub(used_width)
+                    .max(MINIMUM_ACCOUNT_SEPARATION);
+
+                let mut formatted = format!(
+                    "{}{}{}{}",
+                    indent,
+                    account,
+                    " ".repeat(separation),
+                    formatted_amount
+                );
+
+                if let Some(price) = price {
+                    write!(formatted, " {}", self.format_price(price)).unwrap();
+                }
+
+                formatted
+            }
+        }
+    }
+
+    #[must_use]
+    pub fn format_entry(&self, entry: &LedgerEntry) -> String {
+        let mut formatted = entry.date.format("%Y/%m/%d").to_string();
+
+        if let Some(status) = entry.status {
+            write!(formatted, " {}", status).unwrap();
+        }
+        if let Some(code) = &entry.code {
+            write!(formatted, " ({})", code).unwrap();
+        }
+        writeln!(formatted, " {}", entry.payee).unwrap();
+
+        for line in &entry.lines {
+            formatted.push_str(&self.format_entry_line(line));
+            formatted.push('\n');
+        }
+
+        formatted
+    }
+}
@@ -0,0 +1,265 @@
+pub mod amount;
+pub mod format;
+pub mod reader;
+
+pub use amount::{Amount, Commodity, CommodityPosition, PostingPrice};
+pub use format::{LedgerFormat, NegativeStyle};
+pub use reader::{read_journal, Journal};
+
+use chrono::NaiveDate;
+use color_eyre::eyre::{eyre, Result};
+use format::DEFAULT_LEDGER_FORMAT;
+use std::borrow::Borrow;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EntryLine {
+    Comment {
+        comment: String,
+    },
+    Posting {
+        account: String,
+        amount: Amount,
+        price: Option<PostingPrice>,
+    },
+}
+
+impl EntryLine {
+    // The amount this line contributes to the balance of its entry, if it is a posting.
+    #[must_use]
+    pub fn balancing_amount(&self) -> Option<Amount> {
+        match self {
+            EntryLine::Comment { .. } => None,
+            EntryLine::Posting {
+                amount,
+                price: Some(price),
+                ..
+            } => Some(price.cost_of(amount)),
+            EntryLine::Posting {
+                amount,
+                price: None,
+                ..
+            } => Some(amount.clone()),
+        }
+    }
+}
+
+impl fmt::Display for EntryLine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", DEFAULT_LEDGER_FORMAT.format_entry_line(self))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryStatus {
+    Cleared,
+    Pending,
+}
+
+impl fmt::Display for EntryStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryStatus::Cleared => write!(f, "*"),
+            EntryStatus::Pending => write!(f, "!"),
+        }
+    }
+}
+
+pub struct LedgerEntryBuilder {
+    pub date: NaiveDate,
+    pub status: Option<EntryStatus>,
+    pub code: Option<String>,
+    pub payee: String,
+    pub lines: Vec<EntryLine>,
+}
+
+#[derive(Debug)]
+pub struct LedgerEntry {
+    pub date: NaiveDate,
+    pub status: Option<EntryStatus>,
+    pub code: Option<String>,
+    pub payee: String,
+    pub lines: Vec<EntryLine>,
+}
+
+impl LedgerEntry {
+    // Adds a comment after the existing comments, before the first posting.
+    pub fn add_comment(&mut self, comment: String) {
+        let position = self
+            .lines
+            .iter()
+            .position(|line| matches!(line, EntryLine::Posting { .. }))
+            .unwrap_or(self.lines.len());
+        self.lines.insert(position, EntryLine::Comment { comment });
+    }
+}
+
+impl Ord for LedgerEntry {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.date, &self.payee).cmp(&(other.date, &other.payee))
+    }
+}
+
+impl PartialOrd for LedgerEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for LedgerEntry {
+    fn eq(&self, other: &Self) -> bool {
+        (self.date, &self.payee) == (other.date, &other.payee)
+    }
+}
+
+impl Eq for LedgerEntry {}
+
+impl LedgerEntryBuilder {
+    #[must_use]
+    pub fn new(date: NaiveDate, payee: String) -> Self {
+        let lines = Vec::new();
+        Self {
+            date,
+            status: None,
+            code: None,
+            payee,
+            lines,
+        }
+    }
+
+    pub fn set_status(&mut self, status: EntryStatus) {
+        self.status = Some(status);
+    }
+
+    pub fn set_code(&mut self, code: String) {
+        self.code = Some(code);
+    }
+
+    pub fn add_comment(&mut self, comment: String) {
+        let entry_line = EntryLine::Comment { comment };
+        self.lines.push(entry_line);
+    }
+
+    pub fn add_posting(&mut self, account: String, amount: Amount) {
+        let entry_line = EntryLine::Posting {
+            account,
+            amount,
+            price: None,
+        };
+        self.lines.push(entry_line);
+    }
+
+    pub fn add_posting_with_price(&mut self, account: String, amount: Amount, price: PostingPrice) {
+        let entry_line = EntryLine::Posting {
+            account,
+            amount,
+            price: Some(price),
+        };
+        self.lines.push(entry_line);
+    }
+
+    pub fn build(self) -> Result<LedgerEntry> {
+        self.validate()?;
+
+        Ok(LedgerEntry {
+            date: self.date,
+            status: self.status,
+            code: self.code,
+            payee: self.payee,
+            lines: self.lines,
+        })
+    }
+
+    fn validate(&self) -> Result<()> {
+        // Simple validation rules:
+        // The Payee must be non-empty
+        if self.payee.is_empty() {
+            return Err(eyre!("Payee must be non-empty."));
+        }
+
+        // Totals are kept per commodity, as postings in different commodities
+        // only balance each other through a price or cost.
+        let balancing_amounts: Vec<Amount> = self
+            .lines
+            .iter()
+            .filter_map(EntryLine::balancing_amount)
+            .collect();
+        let count = balancing_amounts.len();
+        let mut totals: BTreeMap<&str, Amount> = BTreeMap::new();
+
+        for amount in &balancing_amounts {
+            totals
+                .entry(amount.commodity.symbol.as_str())
+                .and_modify(|total| total.quantity += amount.quantity)
+                .or_insert_with(|| amount.clone());
+        }
+
+        // The lines must have at least 2 Postings
+        if count < 2 {
+            return Err(eyre!(
+                "Ledger entry must have 2 or more postings. Found {}.",
+                count
+            ));
+        }
+
+        // The Postings must balance, or amounts must add up to 0.0 in each commodity
+        if let Some(total) = totals
+            .values()
+            .find(|total| total.quantity.abs() >= total.commodity.tolerance())
+        {
+            return Err(eyre!(
+                "Ledger entry posting lines must balance, found total of {}",
+                total
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Display for LedgerEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", DEFAULT_LEDGER_FORMAT.format_entry(self))
+    }
+}
+
+pub fn write_ledger_entries_file<I>(
+    filename: &Path,
+    entries: I,
+    format: &LedgerFormat,
+) -> Result<()>
+where
+    I: IntoIterator,
+    I::Item: Borrow<LedgerEntry>,
+{
+    // TODO: Check to avoid overwriting existing file
+    let mut output_file = File::create(filename)?;
+
+    for entry in entries {
+        writeln!(output_file, "{}", format.format_entry(entry.borrow()))?;
+    }
+
+    Ok(())
+}
+
+// Adds the entries to the end of the file, creating it if needed.
+pub fn append_ledger_entries_file(
+    filename: &Path,
+    entries: &[LedgerEntry],
+    format: &LedgerFormat,
+) -> Result<()> {
+    let mut output_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(filename)?;
+
+    for entry in entries {
+        writeln!(output_file, "{}", format.format_entry(entry))?;
+    }
+
+    Ok(())
+}
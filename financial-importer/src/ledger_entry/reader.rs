@@ -0,0 +1,872 @@
+use super::amount::{Amount, Commodity, CommodityPosition, PostingPrice};
+use super::format::LedgerFormat;
+use super::{EntryLine, EntryStatus, LedgerEntry};
+use chrono::NaiveDate;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use log::trace;
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+// The contents of a Ledger journal, including any files it includes.
+#[derive(Debug, Default)]
+pub struct Journal {
+    pub entries: Vec<LedgerEntry>,
+    // Accounts declared with `account` directives.
+    pub accounts: BTreeSet<String>,
+    // Commodities declared with `commodity` directives.
+    pub commodities: BTreeSet<String>,
+}
+
+pub fn read_journal(path: &Path, format: &LedgerFormat) -> Result<Journal> {
+    trace!("Reading Ledger journal '{}'.", path.to_str().unwrap());
+
+    let mut reader = JournalReader {
+        format,
+        journal: Journal::default(),
+        include_stack: Vec::new(),
+        default_year: None,
+    };
+    reader.read_file(path)?;
+
+    trace!(
+        "Read {} entries, {} account and {} commodity declarations.",
+        reader.journal.entries.len(),
+        reader.journal.accounts.len(),
+        reader.journal.commodities.len()
+    );
+
+    Ok(reader.journal)
+}
+
+struct JournalReader<'a> {
+    format: &'a LedgerFormat,
+    journal: Journal,
+    // The files currently being read, to catch files that include themselves.
+    include_stack: Vec<PathBuf>,
+    // Set by the `year` directive, for dates written without one.
+    default_year: Option<i32>,
+}
+
+// What the lines following an unindented line belong to.
+enum Block {
+    Idle,
+    Entry(PendingEntry),
+    // Indented lines of directives and of automated or periodic transactions.
+    Skipped,
+    // Everything up to `end comment` or `end test`.
+    Comment,
+}
+
+enum PendingLine {
+    Line(EntryLine),
+    ElidedPosting { account: String },
+}
+
+struct PendingEntry {
+    line_number: usize,
+    entry: LedgerEntry,
+    lines: Vec<PendingLine>,
+}
+
+impl JournalReader<'_> {
+    fn read_file(&mut self, path: &Path) -> Result<()> {
+        let canonical_path = path.canonicalize().wrap_err_with(|| {
+            format!(
+                "Encountered errors reading journal file '{}'.",
+                path.to_str().unwrap()
+            )
+        })?;
+
+        if self.include_stack.contains(&canonical_path) {
+            return Err(eyre!(
+                "Journal file '{}' includes itself.",
+                path.to_str().unwrap()
+            ));
+        }
+
+        let contents = std::fs::read_to_string(path).wrap_err_with(|| {
+            format!(
+                "Encountered errors reading journal file '{}'.",
+                path.to_str().unwrap()
+            )
+        })?;
+
+        self.include_stack.push(canonical_path);
+
+        let mut block = Block::Idle;
+        for (index, line) in contents.lines().enumerate() {
+            block = self
+                .read_line(block, index + 1, line, path)
+                .wrap_err_with(|| {
+                    format!(
+                        "Error in journal file '{}' at line {}.",
+                        path.to_str().unwrap(),
+                        index + 1
+                    )
+                })?;
+        }
+        self.finish_block(block).wrap_err_with(|| {
+            format!(
+                "Error in journal file '{}' at end of file.",
+                path.to_str().unwrap()
+            )
+        })?;
+
+        self.include_stack.pop();
+
+        Ok(())
+    }
+
+    fn read_line(
+        &mut self,
+        block: Block,
+        line_number: usize,
+        line: &str,
+        path: &Path,
+    ) -> Result<Block> {
+        let line = line.trim_end();
+
+        if let Block::Comment = block {
+            return Ok(match line {
+                "end comment" | "end test" => Block::Idle,
+                _ => Block::Comment,
+            });
+        }
+
+        if line.is_empty() {
+            self.finish_block(block)?;
+            return Ok(Block::Idle);
+        }
+
+        if line.starts_with(char::is_whitespace) {
+            return match block {
+                Block::Entry(mut pending) => {
+                    self.read_entry_line(&mut pending, line.trim_start())?;
+                    Ok(Block::Entry(pending))
+                }
+                other => Ok(other),
+            };
+        }
+
+        // Any unindented line ends the block before it
+        self.finish_block(block)?;
+
+        match line.chars().next().unwrap() {
+            ';' | '#' | '%' | '|' | '*' => Ok(Block::Idle),
+            '0'..='9' => Ok(Block::Entry(self.read_entry_header(line_number, line)?)),
+            '~' | '=' => Ok(Block::Skipped),
+            _ => self.read_directive(line, path),
+        }
+    }
+
+    fn read_directive(&mut self, line: &str, path: &Path) -> Result<Block> {
+        let (directive, argument) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(directive, argument)| (directive, argument));
+        let (argument, _) = split_comment(argument.trim());
+
+        match directive {
+            "include" => {
+                let include_path = path
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(argument);
+                self.read_file(&include_path)?;
+                Ok(Block::Idle)
+            }
+            "account" => {
+                self.journal.accounts.insert(String::from(argument));
+                Ok(Block::Skipped)
+            }
+            "commodity" => {
+                let is_quoted =
+                    argument.len() > 1 && argument.starts_with('"') && argument.ends_with('"');
+                let symbol = if !is_quoted && argument.chars().any(|c| c.is_ascii_digit()) {
+                    // A sample amount, such as `commodity $1,000.00`
+                    parse_amount(argument, self.format)?.commodity.symbol
+                } else {
+                    String::from(argument.trim_matches('"'))
+                };
+                self.journal.commodities.insert(symbol);
+                Ok(Block::Skipped)
+            }
+            "year" | "Y" => {
+                let year: i32 = argument
+                    .parse()
+                    .map_err(|_| eyre!("Invalid year '{}'.", argument))?;
+                self.default_year = Some(year);
+                Ok(Block::Idle)
+            }
+            "comment" | "test" => Ok(Block::Comment),
+            _ => {
+                trace!("Skipping unsupported directive '{}'.", directive);
+                Ok(Block::Skipped)
+            }
+        }
+    }
+
+    fn read_entry_header(&self, line_number: usize, line: &str) -> Result<PendingEntry> {
+        let (date_text, rest) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(date_text, rest)| (date_text, rest));
+        // Only the primary date is kept, not an auxiliary `=date`
+        let date = self.parse_date(date_text.split('=').next().unwrap())?;
+
+        let (mut rest, note) = split_comment(rest.trim());
+
+        let mut status = None;
+        if let Some(remaining) = rest.strip_prefix('*') {
+            status = Some(EntryStatus::Cleared);
+            rest = remaining.trim_start();
+        } else if let Some(remaining) = rest.strip_prefix('!') {
+            status = Some(EntryStatus::Pending);
+            rest = remaining.trim_start();
+        }
+
+        let mut code = None;
+        if let Some(remaining) = rest.strip_prefix('(') {
+            let (code_text, remaining) = remaining
+                .split_once(')')
+                .ok_or_else(|| eyre!("Unterminated code in entry header '{}'.", line))?;
+            code = Some(String::from(code_text));
+            rest = remaining.trim_start();
+        }
+
+        let mut lines = Vec::new();
+        if let Some(note) = note {
+            lines.push(PendingLine::Line(EntryLine::Comment {
+                comment: String::from(note),
+            }));
+        }
+
+        Ok(PendingEntry {
+            line_number,
+            entry: LedgerEntry {
+                date,
+                status,
+                code,
+                payee: String::from(rest),
+                lines: Vec::new(),
+            },
+            lines,
+        })
+    }
+
+    fn parse_date(&self, text: &str) -> Result<NaiveDate> {
+        let parts: Vec<&str> = text.split(['/', '-', '.']).collect();
+        let numbers: Vec<u32> = parts
+            .iter()
+            .map(|part| part.parse::<u32>())
+            .collect::<Result<_, _>>()
+            .map_err(|_| eyre!("Invalid date '{}'.", text))?;
+
+        let date = match numbers[..] {
+            [year, month, day] => NaiveDate::from_ymd_opt(year as i32, month, day),
+            [month, day] => {
+                let year = self
+                    .default_year
+                    .ok_or_else(|| eyre!("Date '{}' has no year and no year directive.", text))?;
+                NaiveDate::from_ymd_opt(year, month, day)
+            }
+            _ => None,
+        };
+
+        date.ok_or_else(|| eyre!("Invalid date '{}'.", text))
+    }
+
+    fn read_entry_line(&self, pending: &mut PendingEntry, text: &str) -> Result<()> {
+        if let Some(comment) = text.strip_prefix(';') {
+            pending.lines.push(PendingLine::Line(EntryLine::Comment {
+                comment: String::from(comment.trim()),
+            }));
+            return Ok(());
+        }
+
+        let (posting, comment) = split_comment(text);
+        // Posting-level status markers are not kept
+        let posting = posting
+            .strip_prefix(['*', '!'])
+            .map_or(posting, str::trim_start);
+
+        // The account name ends at two spaces or a tab
+        let account_end = [posting.find("  "), posting.find('\t')]
+            .into_iter()
+            .flatten()
+            .min()
+            .unwrap_or(posting.len());
+        let account = String::from(posting[..account_end].trim());
+        let amount_text = posting[account_end..].trim();
+
+        // A balance assignment without an amount, such as `= $1,000.00`, is
+        // balanced like an elided amount, as its assertion is not kept
+        if amount_text.is_empty() || balance_assertion_start(amount_text) == Some(0) {
+            pending.lines.push(PendingLine::ElidedPosting { account });
+        } else {
+            let (amount, price) = parse_posting_amount(amount_text, self.format)?;
+            pending.lines.push(PendingLine::Line(EntryLine::Posting {
+                account,
+                amount,
+                price,
+            }));
+        }
+
+        if let Some(comment) = comment {
+            pending.lines.push(PendingLine::Line(EntryLine::Comment {
+                comment: String::from(comment),
+            }));
+        }
+
+        Ok(())
+    }
+
+    fn finish_block(&mut self, block: Block) -> Result<()> {
+        if let Block::Entry(pending) = block {
+            let line_number = pending.line_number;
+            let entry = pending
+                .into_entry()
+                .wrap_err_with(|| format!("Invalid entry starting at line {}.", line_number))?;
+            self.journal.entries.push(entry);
+        }
+
+        Ok(())
+    }
+}
+
+impl PendingEntry {
+    // Fills in the amount of a posting left empty, which Ledger balances automatically.
+    fn into_entry(self) -> Result<LedgerEntry> {
+        let PendingEntry {
+            mut entry, lines, ..
+        } = self;
+
+        let elided_count = lines
+            .iter()
+            .filter(|line| matches!(line, PendingLine::ElidedPosting { .. }))
+            .count();
+        if elided_count > 1 {
+            return Err(eyre!(
+                "Only one posting may have its amount elided, found {}.",
+                elided_count
+            ));
+        }
+
+        let mut totals: BTreeMap<String, Amount> = BTreeMap::new();
+        for line in &lines {
+            if let PendingLine::Line(
+                posting @ EntryLine::Posting {
+                    account: posting_account,
+                    ..
+                },
+            ) = line
+            {
+                // Unbalanced virtual postings do not need to balance
+                if posting_account.starts_with('(') {
+                    continue;
+                }
+                let amount = posting.balancing_amount().unwrap();
+                totals
+                    .entry(amount.commodity.symbol.clone())
+                    .and_modify(|total| {
+                        total.quantity += amount.quantity;
+                        total.commodity.precision =
+                            total.commodity.precision.max(amount.commodity.precision);
+                    })
+                    .or_insert(amount);
+            }
+        }
+
+        for line in lines {
+            match line {
+                PendingLine::Line(line) => entry.lines.push(line),
+                PendingLine::ElidedPosting { account } => {
+                    let remaining: Vec<&Amount> = totals
+                        .values()
+                        .filter(|total| total.quantity.abs() >= total.commodity.tolerance())
+                        .collect();
+                    if remaining.is_empty() {
+                        return Err(eyre!(
+                            "Cannot balance the elided amount for account '{}'.",
+                            account
+                        ));
+                    }
+
+                    // One posting per commodity, as Ledger does
+                    for total in remaining {
+                        entry.lines.push(EntryLine::Posting {
+                            account: account.clone(),
+                            amount: -total.clone(),
+                            price: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(entry)
+    }
+}
+
+// Splits off a trailing `; comment`.
+fn split_comment(text: &str) -> (&str, Option<&str>) {
+    match text.split_once(';') {
+        Some((text, comment)) => (text.trim_end(), Some(comment.trim())),
+        None => (text, None),
+    }
+}
+
+// Parses the amount of a posting with its optional lot cost and price,
+// ignoring any lot date, lot note or balance assertion.
+fn parse_posting_amount(
+    text: &str,
+    format: &LedgerFormat,
+) -> Result<(Amount, Option<PostingPrice>)> {
+    let text = balance_assertion_start(text).map_or(text, |start| &text[..start]);
+    let (text, price_text) = text.split_once('@').unwrap_or((text, ""));
+    let (amount_text, lot_text) = text.split_once('{').unwrap_or((text, ""));
+
+    let amount = parse_amount(amount_text, format)?;
+
+    let price = if let Some(total_price) = price_text.strip_prefix('@') {
+        Some(PostingPrice::Total(parse_amount(total_price, format)?))
+    } else if !price_text.is_empty() {
+        Some(PostingPrice::PerUnit(parse_amount(price_text, format)?))
+    } else if let Some(total_cost) = lot_text.strip_prefix('{') {
+        // `{{...}}` is the total cost of the lot
+        let (total_cost, _) = total_cost.split_once('}').unwrap_or((total_cost, ""));
+        Some(PostingPrice::Total(parse_amount(
+            total_cost.trim_start_matches('='),
+            format,
+        )?))
+    } else if !lot_text.is_empty() {
+        let (unit_cost, _) = lot_text.split_once('}').unwrap_or((lot_text, ""));
+        Some(PostingPrice::LotCost(parse_amount(
+            unit_cost.trim_start_matches('='),
+            format,
+        )?))
+    } else {
+        None
+    };
+
+    Ok((amount, price))
+}
+
+// The position of the `=` of a balance assertion, skipping the `=` of a fixed
+// lot cost such as `{=$10}`.
+fn balance_assertion_start(text: &str) -> Option<usize> {
+    let mut lot_depth = 0_usize;
+    text.char_indices().find_map(|(index, c)| {
+        match c {
+            '{' => lot_depth += 1,
+            '}' => lot_depth = lot_depth.saturating_sub(1),
+            '=' if lot_depth == 0 => return Some(index),
+            _ => (),
+        }
+        None
+    })
+}
+
+pub fn parse_amount(text: &str, format: &LedgerFormat) -> Result<Amount> {
+    let original_text = text;
+    let text = text.trim();
+
+    if text.starts_with('(') {
+        return Err(eyre!(
+            "Value expressions such as '{}' are not supported.",
+            original_text
+        ));
+    }
+
+    let is_number_char = |c: char| {
+        c.is_ascii_digit() || c == format.decimal_mark || format.thousands_separator.contains(c)
+    };
+
+    let (mut negative, text) = match text.strip_prefix('-') {
+        Some(text) => (true, text.trim_start()),
+        None => (false, text),
+    };
+
+    let (number_text, symbol, position) = if text.starts_with(is_number_char) {
+        let number_end = text.find(|c| !is_number_char(c)).unwrap_or(text.len());
+        let symbol = text[number_end..].trim();
+        (&text[..number_end], symbol, CommodityPosition::Suffix)
+    } else {
+        let symbol_end = if let Some(quoted) = text.strip_prefix('"') {
+            quoted
+                .find('"')
+                .map(|end| end + 2)
+                .ok_or_else(|| eyre!("Unterminated commodity in amount '{}'.", original_text))?
+        } else {
+            text.find(|c: char| is_number_char(c) || c == '-' || c.is_whitespace())
+                .unwrap_or(text.len())
+        };
+
+        let mut number_text = text[symbol_end..].trim_start();
+        if let Some(unsigned_text) = number_text.strip_prefix('-') {
+            negative = true;
+            number_text = unsigned_text;
+        }
+        (number_text, &text[..symbol_end], CommodityPosition::Prefix)
+    };
+
+    let symbol = symbol.trim_matches('"');
+    if number_text.is_empty()
+        || !number_text.chars().all(is_number_char)
+        || (position == CommodityPosition::Suffix
+            && symbol.contains(char::is_whitespace)
+            && !text.ends_with('"'))
+    {
+        return Err(eyre!("Could not parse amount '{}'.", original_text));
+    }
+
+    let digits: String = number_text
+        .chars()
+        .filter(|c| !format.thousands_separator.contains(*c))
+        .map(|c| if c == format.decimal_mark { '.' } else { c })
+        .collect();
+    let quantity: f64 = digits
+        .parse()
+        .map_err(|_| eyre!("Could not parse amount '{}'.", original_text))?;
+    let precision = digits
+        .split_once('.')
+        .map_or(0, |(_, fraction)| fraction.len());
+
+    let mut commodity = Commodity::new(symbol);
+    commodity.position = position;
+    commodity.precision = precision;
+
+    Ok(Amount::new(
+        if negative { -quantity } else { quantity },
+        commodity,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Reads the journal from a file of the given name in a directory of its own.
+    fn read_journal_text(name: &str, contents: &str) -> Result<Journal> {
+        let directory =
+            std::env::temp_dir().join(format!("financial-importer-{}", std::process::id()));
+        std::fs::create_dir_all(&directory)?;
+        let path = directory.join(name);
+        std::fs::write(&path, contents)?;
+        read_journal(&path, &LedgerFormat::default())
+    }
+
+    fn amount(text: &str) -> Amount {
+        parse_amount(text, &LedgerFormat::default()).unwrap()
+    }
+
+    fn posting(account: &str, quantity: &str) -> EntryLine {
+        EntryLine::Posting {
+            account: String::from(account),
+            amount: amount(quantity),
+            price: None,
+        }
+    }
+
+    fn posting_with_price(account: &str, quantity: &str, price: PostingPrice) -> EntryLine {
+        EntryLine::Posting {
+            account: String::from(account),
+            amount: amount(quantity),
+            price: Some(price),
+        }
+    }
+
+    #[test]
+    fn elided_amount_balances_the_other_postings() {
+        let journal = read_journal_text(
+            "elided.dat",
+            concat!(
+                "2024/01/02 Grocer\n",
+                "    Expenses:Food    $45.10\n",
+                "    Expenses:Travel    10.00 EUR\n",
+                "    Assets:Checking\n",
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            journal.entries[0].lines,
+            vec![
+                posting("Expenses:Food", "$45.10"),
+                posting("Expenses:Travel", "10.00 EUR"),
+                posting("Assets:Checking", "$-45.10"),
+                posting("Assets:Checking", "-10.00 EUR"),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_one_posting_may_be_elided() {
+        let result = read_journal_text(
+            "two-elided.dat",
+            concat!(
+                "2024/01/02 Grocer\n",
+                "    Expenses:Food\n",
+                "    Assets:Checking\n",
+            ),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn balance_assignment_without_an_amount_is_elided() {
+        let journal = read_journal_text(
+            "balance-assignment.dat",
+            concat!(
+                "2024/01/31 Interest\n",
+                "    Assets:Checking    = $1,000.00\n",
+                "    Income:Interest    $-2.50\n",
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            journal.entries[0].lines,
+            vec![
+                posting("Assets:Checking", "$2.50"),
+                posting("Income:Interest", "$-2.50"),
+            ]
+        );
+    }
+
+    #[test]
+    fn balance_assertion_after_an_amount_is_not_kept() {
+        let journal = read_journal_text(
+            "balance-assertion.dat",
+            concat!(
+                "2024/01/02 Grocer\n",
+                "    Expenses:Food    $45.10\n",
+                "    Assets:Checking    $-45.10 = $954.90\n",
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            journal.entries[0].lines,
+            vec![
+                posting("Expenses:Food", "$45.10"),
+                posting("Assets:Checking", "$-45.10"),
+            ]
+        );
+    }
+
+    #[test]
+    fn included_files_are_read_relative_to_the_including_file() {
+        read_journal_text(
+            "included.dat",
+            concat!(
+                "2024/01/02 Grocer\n",
+                "    Expenses:Food    $45.10\n",
+                "    Assets:Checking\n",
+            ),
+        )
+        .unwrap();
+        let journal = read_journal_text(
+            "including.dat",
+            concat!(
+                "include included.dat\n",
+                "\n",
+                "2024/01/03 Cafe\n",
+                "    Expenses:Dining    $12.00\n",
+                "    Assets:Checking\n",
+            ),
+        )
+        .unwrap();
+
+        let payees: Vec<&str> = journal
+            .entries
+            .iter()
+            .map(|entry| entry.payee.as_str())
+            .collect();
+        assert_eq!(payees, vec!["Grocer", "Cafe"]);
+    }
+
+    #[test]
+    fn file_including_itself_is_an_error() {
+        let result = read_journal_text("include-self.dat", "include include-self.dat\n");
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn account_and_commodity_directives_are_collected() {
+        let journal = read_journal_text(
+            "declarations.dat",
+            concat!(
+                "account Assets:Checking\n",
+                "    note The joint account\n",
+                "account Expenses:Food ; groceries\n",
+                "commodity $1,000.00\n",
+                "commodity EUR\n",
+                "commodity \"VANGUARD 500\"\n",
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            journal.accounts,
+            BTreeSet::from([
+                String::from("Assets:Checking"),
+                String::from("Expenses:Food"),
+            ])
+        );
+        assert_eq!(
+            journal.commodities,
+            BTreeSet::from([
+                String::from("$"),
+                String::from("EUR"),
+                String::from("VANGUARD 500"),
+            ])
+        );
+        assert!(journal.entries.is_empty());
+    }
+
+    #[test]
+    fn year_directive_sets_the_year_of_dates_without_one() {
+        let journal = read_journal_text(
+            "year.dat",
+            concat!(
+                "year 2023\n",
+                "\n",
+                "12/31 Grocer\n",
+                "    Expenses:Food    $45.10\n",
+                "    Assets:Checking\n",
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            journal.entries[0].date,
+            NaiveDate::from_ymd_opt(2023, 12, 31).unwrap()
+        );
+    }
+
+    #[test]
+    fn date_without_a_year_needs_a_year_directive() {
+        let result = read_journal_text(
+            "no-year.dat",
+            concat!(
+                "12/31 Grocer\n",
+                "    Expenses:Food    $45.10\n",
+                "    Assets:Checking\n",
+            ),
+        );
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn status_and_code_are_read_from_the_header() {
+        let journal = read_journal_text(
+            "status-and-code.dat",
+            concat!(
+                "2024/01/02=2024/01/04 * (1042) Landlord ; January\n",
+                "    Expenses:Rent    $1,200.00\n",
+                "    ! Assets:Checking\n",
+                "\n",
+                "2024/01/05 ! Grocer\n",
+                "    Expenses:Food    $45.10\n",
+                "    Assets:Checking\n",
+            ),
+        )
+        .unwrap();
+
+        let rent = &journal.entries[0];
+        assert_eq!(rent.date, NaiveDate::from_ymd_opt(2024, 1, 2).unwrap());
+        assert_eq!(rent.status, Some(EntryStatus::Cleared));
+        assert_eq!(rent.code.as_deref(), Some("1042"));
+        assert_eq!(rent.payee, "Landlord");
+        assert_eq!(
+            rent.lines,
+            vec![
+                EntryLine::Comment {
+                    comment: String::from("January"),
+                },
+                posting("Expenses:Rent", "$1,200.00"),
+                posting("Assets:Checking", "$-1,200.00"),
+            ]
+        );
+
+        let groceries = &journal.entries[1];
+        assert_eq!(groceries.status, Some(EntryStatus::Pending));
+        assert_eq!(groceries.code, None);
+        assert_eq!(groceries.payee, "Grocer");
+    }
+
+    #[test]
+    fn prices_and_lot_costs_are_read() {
+        let journal = read_journal_text(
+            "prices.dat",
+            concat!(
+                "2024/01/06 Cafe Paris\n",
+                "    Expenses:Dining    10.00 EUR @ $1.25\n",
+                "    Liabilities:Visa\n",
+                "\n",
+                "2024/01/07 Hotel\n",
+                "    Expenses:Travel    100.00 EUR @@ $108.60\n",
+                "    Liabilities:Visa\n",
+                "\n",
+                "2024/01/08 Broker\n",
+                "    Assets:Brokerage    10 AAPL {$150.00} [2024/01/08]\n",
+                "    Assets:Brokerage    2 MSFT {{$800.00}}\n",
+                "    Assets:Checking\n",
+            ),
+        )
+        .unwrap();
+
+        assert_eq!(
+            journal.entries[0].lines,
+            vec![
+                posting_with_price(
+                    "Expenses:Dining",
+                    "10.00 EUR",
+                    PostingPrice::PerUnit(amount("$1.25"))
+                ),
+                posting("Liabilities:Visa", "$-12.50"),
+            ]
+        );
+        assert_eq!(
+            journal.entries[1].lines,
+            vec![
+                posting_with_price(
+                    "Expenses:Travel",
+                    "100.00 EUR",
+                    PostingPrice::Total(amount("$108.60"))
+                ),
+                posting("Liabilities:Visa", "$-108.60"),
+            ]
+        );
+        assert_eq!(
+            journal.entries[2].lines,
+            vec![
+                posting_with_price(
+                    "Assets:Brokerage",
+                    "10 AAPL",
+                    PostingPrice::LotCost(amount("$150.00"))
+                ),
+                posting_with_price(
+                    "Assets:Brokerage",
+                    "2 MSFT",
+                    PostingPrice::Total(amount("$800.00"))
+                ),
+                posting("Assets:Checking", "$-2,300.00"),
+            ]
+        );
+    }
+
+    #[test]
+    fn fixed_lot_cost_is_not_taken_for_a_balance_assertion() {
+        let format = LedgerFormat::default();
+        let (amount, price) = parse_posting_amount("10 AAPL {=$10} = 20 AAPL", &format).unwrap();
+
+        assert_eq!(amount, parse_amount("10 AAPL", &format).unwrap());
+        assert_eq!(
+            price,
+            Some(PostingPrice::LotCost(parse_amount("$10", &format).unwrap()))
+        );
+    }
+}
@@ -1,4 +1,14 @@
 pub mod app;
+pub mod classifier;
+pub mod history;
+pub mod import_session;
 pub mod ledger_entry;
+pub mod ofx;
+pub mod reconciliation;
+pub mod recurring;
+pub mod review;
+pub mod rule_generation;
 pub mod source_record;
 pub mod transaction_matcher;
+pub mod transfers;
+pub mod watch;
@@ -0,0 +1,188 @@
+use crate::source_record::{LoadedSourceRecords, RejectedSourceRecord, SourceRecord};
+use chrono::NaiveDate;
+use color_eyre::eyre::{eyre, Result};
+use lazy_static::lazy_static;
+use log::trace;
+use regex::Regex;
+use std::collections::BTreeMap;
+
+lazy_static! {
+    static ref OFX_HEADER: Regex = Regex::new(r"(?i)(OFXHEADER|<OFX>)").unwrap();
+    static ref OFX_TRANSACTION: Regex = Regex::new(r"(?is)<STMTTRN>(.*?)</STMTTRN>").unwrap();
+    // The value of an element ends at the next tag or line in the SGML of
+    // OFX 1, and at its closing tag in the XML of OFX 2.
+    static ref OFX_ELEMENT: Regex = Regex::new(r"<([A-Za-z0-9.]+)>([^<\r\n]*)").unwrap();
+}
+
+// The columns of the records loaded from an OFX file, and the elements they come
+// from, used as the header of a rejects file.
+static OFX_COLUMNS: [(&str, &str); 4] = [
+    ("date", "DTPOSTED"),
+    ("description", "NAME"),
+    ("amount", "TRNAMT"),
+    ("fitid", "FITID"),
+];
+
+#[must_use]
+pub fn is_ofx(text: &str) -> bool {
+    OFX_HEADER.is_match(text)
+}
+
+// Loads the `STMTTRN` transactions of an OFX statement, described by their
+// `NAME`, or their `MEMO` when there is none, and keeping their `FITID` to
+// recognize them in later statements.
+#[must_use]
+pub fn read_ofx_records(text: &str) -> LoadedSourceRecords {
+    let mut records = Vec::new();
+    let mut rejected = Vec::new();
+
+    for transaction in OFX_TRANSACTION.captures_iter(text) {
+        let start = transaction.get(0).unwrap().start();
+        let line_number = text[..start].matches('\n').count() as u64 + 1;
+        let elements: BTreeMap<String, String> = OFX_ELEMENT
+            .captures_iter(&transaction[1])
+            .map(|element| {
+                (
+                    element[1].to_ascii_uppercase(),
+                    decode_entities(element[2].trim()),
+                )
+            })
+            .collect();
+
+        match ofx_record(&elements) {
+            Ok(record) => records.push(SourceRecord {
+                line_number: Some(line_number),
+                ..record
+            }),
+            Err(error) => rejected.push(RejectedSourceRecord {
+                line_number: Some(line_number),
+                reason: error.to_string(),
+                fields: OFX_COLUMNS
+                    .iter()
+                    .map(|(_, tag)| elements.get(*tag).cloned().unwrap_or_default())
+                    .collect(),
+            }),
+        }
+    }
+
+    trace!(
+        "Loaded {} OFX transactions, rejected {}.",
+        records.len(),
+        rejected.len()
+    );
+
+    LoadedSourceRecords {
+        headers: OFX_COLUMNS
+            .iter()
+            .map(|(column, _)| String::from(*column))
+            .collect(),
+        records,
+        rejected,
+        duplicates: Vec::new(),
+    }
+}
+
+fn ofx_record(elements: &BTreeMap<String, String>) -> Result<SourceRecord> {
+    let element = |tag: &str| {
+        elements
+            .get(tag)
+            .filter(|value| !value.is_empty())
+            .ok_or_else(|| eyre!("Transaction has no {}.", tag))
+    };
+
+    // Dates are `YYYYMMDD`, optionally followed by the time and time zone
+    let date_text = element("DTPOSTED")?;
+    let date = date_text
+        .get(..8)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .ok_or_else(|| eyre!("Invalid DTPOSTED '{}'.", date_text))?;
+
+    // OFX allows a comma as the decimal mark
+    let amount_text = element("TRNAMT")?;
+    let amount: f64 = amount_text
+        .replace(',', ".")
+        .parse()
+        .map_err(|_| eyre!("Invalid TRNAMT '{}'.", amount_text))?;
+
+    let description = element("NAME").or_else(|_| element("MEMO"))?;
+
+    Ok(SourceRecord {
+        date,
+        description: description.clone(),
+        amount,
+        original_amount: None,
+        original_currency: None,
+        fitid: element("FITID").ok().cloned(),
+        line_number: None,
+    })
+}
+
+fn decode_entities(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type RecordFields<'a> = (NaiveDate, &'a str, f64, Option<&'a str>, Option<u64>);
+
+    #[test]
+    fn transactions_are_read_from_sgml_and_xml() {
+        let loaded = read_ofx_records(concat!(
+            "OFXHEADER:100\n",
+            "<OFX><BANKTRANLIST>\n",
+            "<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240102120000[-5:EST]<TRNAMT>-45.10\n",
+            "<FITID>2024010201<NAME>SMITHS #123 &amp; FUEL</STMTTRN>\n",
+            "<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240103<TRNAMT>1200,00\n",
+            "<FITID>2024010301<MEMO>PAYROLL</STMTTRN>\n",
+            "<STMTTRN>\n",
+            "  <DTPOSTED>20240104</DTPOSTED>\n",
+            "  <TRNAMT>-12.50</TRNAMT>\n",
+            "  <NAME>CAFE</NAME>\n",
+            "</STMTTRN>\n",
+            "<STMTTRN><DTPOSTED>2024<TRNAMT>-1.00<NAME>BAD DATE</STMTTRN>\n",
+            "</BANKTRANLIST></OFX>\n",
+        ));
+
+        let records: Vec<RecordFields> = loaded
+            .records
+            .iter()
+            .map(|record| {
+                (
+                    record.date,
+                    record.description.as_str(),
+                    record.amount,
+                    record.fitid.as_deref(),
+                    record.line_number,
+                )
+            })
+            .collect();
+        let date = |day| NaiveDate::from_ymd_opt(2024, 1, day).unwrap();
+        assert_eq!(
+            records,
+            vec![
+                (
+                    date(2),
+                    "SMITHS #123 & FUEL",
+                    -45.1,
+                    Some("2024010201"),
+                    Some(3)
+                ),
+                (date(3), "PAYROLL", 1200.0, Some("2024010301"), Some(5)),
+                (date(4), "CAFE", -12.5, None, Some(7)),
+            ]
+        );
+
+        assert_eq!(loaded.rejected.len(), 1);
+        assert_eq!(loaded.rejected[0].line_number, Some(12));
+        assert_eq!(
+            loaded.rejected[0].fields,
+            vec!["2024", "BAD DATE", "-1.00", ""]
+        );
+    }
+}
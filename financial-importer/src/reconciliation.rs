@@ -0,0 +1,63 @@
+use crate::source_record::{
+    find_duplicate_records, read_source_records, LoadedSourceRecords, RejectedSourceRecord,
+    SourceRecord,
+};
+use color_eyre::eyre::Result;
+use log::trace;
+use std::path::Path;
+
+// Balances are compared to the cent.
+static RECONCILIATION_TOLERANCE: f64 = 0.005;
+
+pub struct StatementReconciliation {
+    pub opening_balance: f64,
+    pub closing_balance: f64,
+    pub records: Vec<SourceRecord>,
+    pub rejected: Vec<RejectedSourceRecord>,
+}
+
+impl StatementReconciliation {
+    pub fn for_file(input_path: &Path, opening_balance: f64, closing_balance: f64) -> Result<Self> {
+        let LoadedSourceRecords {
+            records, rejected, ..
+        } = read_source_records(input_path)?;
+
+        trace!(
+            "Reconciling {} source records from '{}'.",
+            records.len(),
+            input_path.to_str().unwrap()
+        );
+
+        Ok(StatementReconciliation {
+            opening_balance,
+            closing_balance,
+            records,
+            rejected,
+        })
+    }
+
+    #[must_use]
+    pub fn records_total(&self) -> f64 {
+        self.records.iter().map(|record| record.amount).sum()
+    }
+
+    #[must_use]
+    pub fn computed_closing_balance(&self) -> f64 {
+        self.opening_balance + self.records_total()
+    }
+
+    #[must_use]
+    pub fn difference(&self) -> f64 {
+        self.closing_balance - self.computed_closing_balance()
+    }
+
+    #[must_use]
+    pub fn reconciles(&self) -> bool {
+        self.difference().abs() < RECONCILIATION_TOLERANCE
+    }
+
+    #[must_use]
+    pub fn duplicate_records(&self) -> Vec<(&SourceRecord, &SourceRecord)> {
+        find_duplicate_records(&self.records)
+    }
+}
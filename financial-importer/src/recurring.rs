@@ -0,0 +1,289 @@
+use crate::ledger_entry::{Amount, EntryLine, LedgerEntry, LedgerFormat};
+use crate::rule_generation::description_tokens;
+use crate::transaction_matcher::GeneratedLedgerEntry;
+use chrono::{Days, Months, NaiveDate};
+use log::trace;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use voca_rs::case;
+
+// Words of the description grouping records handled by the fallback rule.
+static GROUPING_TOKEN_COUNT: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Periodicity {
+    Weekly,
+    Monthly,
+    Annual,
+}
+
+impl Periodicity {
+    // The typical number of days between occurrences, and how far off an
+    // occurrence may be.
+    fn days_and_tolerance(self) -> (i64, i64) {
+        match self {
+            Periodicity::Weekly => (7, 1),
+            Periodicity::Monthly => (30, 4),
+            Periodicity::Annual => (365, 10),
+        }
+    }
+
+    // The occurrence `count` periods after the date.
+    fn after(self, date: NaiveDate, count: u32) -> NaiveDate {
+        match self {
+            Periodicity::Weekly => date + Days::new(7 * u64::from(count)),
+            Periodicity::Monthly => date + Months::new(count),
+            Periodicity::Annual => date + Months::new(12 * count),
+        }
+    }
+
+    // The Ledger period expression of a periodic transaction.
+    #[must_use]
+    pub fn period_expression(self) -> &'static str {
+        match self {
+            Periodicity::Weekly => "weekly",
+            Periodicity::Monthly => "monthly",
+            Periodicity::Annual => "yearly",
+        }
+    }
+
+    fn for_interval(days: i64) -> Option<Self> {
+        [
+            Periodicity::Weekly,
+            Periodicity::Monthly,
+            Periodicity::Annual,
+        ]
+        .into_iter()
+        .find(|periodicity| {
+            let (period_days, tolerance) = periodicity.days_and_tolerance();
+            (days - period_days).abs() <= tolerance
+        })
+    }
+
+    // How many periods the interval spans, if it is close to a whole number of them.
+    fn periods_in(self, days: i64) -> Option<i64> {
+        let (period_days, tolerance) = self.days_and_tolerance();
+        let periods = ((days as f64) / (period_days as f64)).round() as i64;
+        (periods >= 1 && (days - periods * period_days).abs() <= tolerance * periods)
+            .then_some(periods)
+    }
+}
+
+impl fmt::Display for Periodicity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Periodicity::Weekly => write!(f, "weekly"),
+            Periodicity::Monthly => write!(f, "monthly"),
+            Periodicity::Annual => write!(f, "annual"),
+        }
+    }
+}
+
+pub struct RecurringOptions {
+    // The fewest occurrences a series needs.
+    pub min_occurrences: usize,
+    // How much, as a fraction of the previous amount, an amount may change
+    // without the change being kept by the next occurrence.
+    pub amount_tolerance: f64,
+    // The date occurrences are expected by, for finding missed ones.
+    pub as_of: NaiveDate,
+}
+
+pub struct RecurringOccurrence {
+    pub date: NaiveDate,
+    // The amount posted to the statement's account.
+    pub amount: Amount,
+    pub entry: LedgerEntry,
+}
+
+pub struct PriceChange {
+    pub date: NaiveDate,
+    pub from: Amount,
+    pub to: Amount,
+}
+
+// Transactions with the same payee recurring at a regular period, with
+// amounts that are stable apart from price changes.
+pub struct RecurringSeries {
+    pub payee: String,
+    pub periodicity: Periodicity,
+    // In date order.
+    pub occurrences: Vec<RecurringOccurrence>,
+    pub price_changes: Vec<PriceChange>,
+    // When occurrences were expected between those seen, but not seen.
+    pub missed_dates: Vec<NaiveDate>,
+    // Occurrences off the schedule of the others, such as a charge repeated
+    // on the same day.
+    pub irregular_dates: Vec<NaiveDate>,
+    // When occurrences were expected after the latest one, up to the as-of
+    // date, as when a subscription was cancelled or moved to another account.
+    pub overdue_dates: Vec<NaiveDate>,
+}
+
+impl RecurringSeries {
+    #[must_use]
+    pub fn latest(&self) -> &RecurringOccurrence {
+        self.occurrences.last().unwrap()
+    }
+
+    // A Ledger periodic transaction with the postings of the latest occurrence,
+    // for budgeting.
+    #[must_use]
+    pub fn periodic_transaction(&self, format: &LedgerFormat) -> String {
+        let mut formatted = String::new();
+        writeln!(formatted, "~ {}", self.periodicity.period_expression()).unwrap();
+        writeln!(
+            formatted,
+            "{}",
+            format.format_entry_line(&EntryLine::Comment {
+                comment: self.payee.clone()
+            })
+        )
+        .unwrap();
+        for line in &self.latest().entry.lines {
+            if let EntryLine::Posting { .. } = line {
+                writeln!(formatted, "{}", format.format_entry_line(line)).unwrap();
+            }
+        }
+
+        formatted
+    }
+}
+
+// Groups generated entries by payee, or for those from the fallback rule by
+// the leading words of their description, to look for recurring series.
+#[derive(Default)]
+pub struct RecurringCandidates {
+    groups: BTreeMap<String, Vec<RecurringOccurrence>>,
+}
+
+impl RecurringCandidates {
+    pub fn add(&mut self, generated: GeneratedLedgerEntry) {
+        let from_matched_rule = generated.is_from_matched_rule();
+        let (entry, record) = generated.unwrap();
+
+        let tokens = description_tokens(&record.description);
+        let payee = if from_matched_rule || tokens.is_empty() {
+            entry.payee.clone()
+        } else {
+            case::title_case(
+                &tokens
+                    .into_iter()
+                    .take(GROUPING_TOKEN_COUNT)
+                    .collect::<Vec<_>>()
+                    .join(" "),
+            )
+        };
+
+        let Some(amount) = entry.lines.iter().find_map(EntryLine::balancing_amount) else {
+            return;
+        };
+
+        self.groups
+            .entry(payee)
+            .or_default()
+            .push(RecurringOccurrence {
+                date: entry.date,
+                amount,
+                entry,
+            });
+    }
+
+    #[must_use]
+    pub fn find_recurring(self, options: &RecurringOptions) -> Vec<RecurringSeries> {
+        let series: Vec<RecurringSeries> = self
+            .groups
+            .into_iter()
+            .filter_map(|(payee, occurrences)| recurring_series(payee, occurrences, options))
+            .collect();
+
+        trace!("Found {} recurring series.", series.len());
+
+        series
+    }
+}
+
+fn recurring_series(
+    payee: String,
+    mut occurrences: Vec<RecurringOccurrence>,
+    options: &RecurringOptions,
+) -> Option<RecurringSeries> {
+    if occurrences.len() < options.min_occurrences.max(2) {
+        return None;
+    }
+    occurrences.sort_by_key(|occurrence| occurrence.date);
+
+    let intervals: Vec<i64> = occurrences
+        .windows(2)
+        .map(|pair| (pair[1].date - pair[0].date).num_days())
+        .collect();
+    let periodicity = Periodicity::for_interval(median(&intervals))?;
+
+    let mut missed_dates = Vec::new();
+    let mut irregular_dates = Vec::new();
+    for (pair, interval) in occurrences.windows(2).zip(&intervals) {
+        match periodicity.periods_in(*interval) {
+            Some(periods) => {
+                missed_dates.extend((1..periods).map(|i| periodicity.after(pair[0].date, i as u32)))
+            }
+            None => irregular_dates.push(pair[1].date),
+        }
+    }
+
+    let (_, tolerance) = periodicity.days_and_tolerance();
+    let last_date = occurrences.last().unwrap().date;
+    let mut overdue_dates = Vec::new();
+    let mut periods = 1;
+    while periodicity.after(last_date, periods) + Days::new(tolerance as u64) < options.as_of {
+        overdue_dates.push(periodicity.after(last_date, periods));
+        periods += 1;
+    }
+
+    let changed = |index: usize| {
+        (occurrences[index].amount.quantity - occurrences[index - 1].amount.quantity).abs()
+            > occurrences[index].amount.commodity.tolerance()
+    };
+    let price_change_indexes: Vec<usize> = (1..occurrences.len())
+        .filter(|&index| changed(index))
+        .collect();
+
+    // A price change is kept by the occurrence after it, while amounts that
+    // keep changing by more than the tolerance are not a recurring charge.
+    let unstable_index = price_change_indexes.iter().find(|&&index| {
+        let kept = index + 1 == occurrences.len() || !changed(index + 1);
+        let (from, to) = (
+            occurrences[index - 1].amount.quantity,
+            occurrences[index].amount.quantity,
+        );
+        !kept && (to - from).abs() > from.abs() * options.amount_tolerance
+    });
+    if unstable_index.is_some() {
+        trace!("Amounts of '{}' vary too much to be recurring.", payee);
+        return None;
+    }
+
+    let price_changes = price_change_indexes
+        .into_iter()
+        .map(|index| PriceChange {
+            date: occurrences[index].date,
+            from: occurrences[index - 1].amount.clone(),
+            to: occurrences[index].amount.clone(),
+        })
+        .collect();
+
+    Some(RecurringSeries {
+        payee,
+        periodicity,
+        occurrences,
+        price_changes,
+        missed_dates,
+        irregular_dates,
+        overdue_dates,
+    })
+}
+
+fn median(values: &[i64]) -> i64 {
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    sorted[sorted.len() / 2]
+}
@@ -0,0 +1,394 @@
+use crate::ledger_entry::{Amount, EntryLine, LedgerEntry, LedgerEntryBuilder, LedgerFormat};
+use crate::rule_generation::{description_pattern, description_tokens};
+use crate::source_record::SourceRecord;
+use crate::transaction_matcher::definitions::{
+    resolve_account_alias, AccountMap, TransactionMatcher, TransactionRuleConfiguration,
+    ALIAS_WILDCARD_SUFFIX,
+};
+use crate::transaction_matcher::matcher::NEEDS_FINALIZED_COMMENT;
+use color_eyre::eyre::{eyre, Result};
+use regex::Regex;
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::history::DefaultHistory;
+use rustyline::{Context, Editor, Helper, Highlighter, Hinter, Validator};
+
+// Words of the description used for the pattern of a saved rule.
+static RULE_PATTERN_TOKEN_COUNT: usize = 2;
+
+// Completes the word under the cursor from the account aliases.
+#[derive(Helper, Highlighter, Hinter, Validator)]
+struct AliasCompleter {
+    aliases: Vec<String>,
+}
+
+impl Completer for AliasCompleter {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos]
+            .rfind(char::is_whitespace)
+            .map_or(0, |index| index + 1);
+        let prefix = &line[start..pos];
+
+        let candidates = self
+            .aliases
+            .iter()
+            .filter(|alias| alias.starts_with(prefix))
+            .cloned()
+            .collect();
+
+        Ok((start, candidates))
+    }
+}
+
+pub struct ReviewOutcome {
+    pub entries: Vec<LedgerEntry>,
+    // Rules the user chose to save from their decisions.
+    pub new_rules: Vec<TransactionRuleConfiguration>,
+    pub reviewed_count: usize,
+    pub unreviewed_count: usize,
+}
+
+enum Decision {
+    Accept,
+    Leave,
+    Quit,
+}
+
+// Walks through fallback entries one at a time, for the user to accept,
+// recategorize, rename or split. The category posting of a fallback entry
+// is its last posting, from the fallback rule's `account2`.
+pub struct ReviewSession<'a> {
+    accounts: &'a AccountMap,
+    matcher: &'a TransactionMatcher,
+    format: &'a LedgerFormat,
+    editor: Editor<AliasCompleter, DefaultHistory>,
+}
+
+impl<'a> ReviewSession<'a> {
+    pub fn new(
+        accounts: &'a AccountMap,
+        matcher: &'a TransactionMatcher,
+        format: &'a LedgerFormat,
+    ) -> Result<Self> {
+        // Wildcard aliases are completed up to their `.`, to be continued by hand
+        let mut aliases: Vec<String> = accounts
+            .keys()
+            .map(|alias| match alias.strip_suffix(ALIAS_WILDCARD_SUFFIX) {
+                Some(prefix) => format!("{}.", prefix),
+                None => alias.clone(),
+            })
+            .collect();
+        aliases.sort();
+
+        let mut editor = Editor::new()?;
+        editor.set_helper(Some(AliasCompleter { aliases }));
+
+        Ok(ReviewSession {
+            accounts,
+            matcher,
+            format,
+            editor,
+        })
+    }
+
+    pub fn review(
+        &mut self,
+        fallback_entries: Vec<(LedgerEntry, &SourceRecord)>,
+        save_rules: bool,
+    ) -> Result<ReviewOutcome> {
+        let total = fallback_entries.len();
+        let mut outcome = ReviewOutcome {
+            entries: Vec::new(),
+            new_rules: Vec::new(),
+            reviewed_count: 0,
+            unreviewed_count: 0,
+        };
+        let mut quitting = false;
+
+        for (index, (mut entry, record)) in fallback_entries.into_iter().enumerate() {
+            if quitting {
+                outcome.unreviewed_count += 1;
+                outcome.entries.push(entry);
+                continue;
+            }
+
+            println!("\n[{}/{}]", index + 1, total);
+            let mut chosen_alias: Option<String> = None;
+
+            let decision = loop {
+                print!("{}", self.format.format_entry(&entry));
+
+                let Some(choice) = self.prompt(
+                    "[a]ccept, [c]ategory, [p]ayee, [s]plit, [n]ext without accepting, [q]uit: ",
+                )?
+                else {
+                    break Decision::Quit;
+                };
+
+                match choice.as_str() {
+                    "a" => break Decision::Accept,
+                    "n" => break Decision::Leave,
+                    "q" => break Decision::Quit,
+                    "c" => {
+                        if let Some((alias, account)) = self.prompt_alias("Account alias: ")? {
+                            match recategorize(entry, &account) {
+                                Ok(recategorized) => entry = recategorized,
+                                Err((unchanged, error)) => {
+                                    entry = unchanged;
+                                    println!("{}", error);
+                                }
+                            }
+                            chosen_alias = Some(alias);
+                        }
+                    }
+                    "p" => {
+                        if let Some(payee) = self.prompt("Payee: ")? {
+                            if !payee.is_empty() {
+                                entry.payee = payee;
+                            }
+                        }
+                    }
+                    "s" => {
+                        entry = self.split(entry)?;
+                        // A split entry cannot be turned into a single rule
+                        chosen_alias = None;
+                    }
+                    _ => println!("Unknown choice '{}'.", choice),
+                }
+            };
+
+            match decision {
+                Decision::Accept => {
+                    entry.lines.retain(|line| {
+                        !matches!(line, EntryLine::Comment { comment } if comment == NEEDS_FINALIZED_COMMENT)
+                    });
+
+                    if let (true, Some(alias)) = (save_rules, chosen_alias) {
+                        if let Some(rule) = self.rule_for_decision(&entry, record, alias)? {
+                            outcome.new_rules.push(rule);
+                        }
+                    }
+                    outcome.reviewed_count += 1;
+                }
+                Decision::Leave => outcome.unreviewed_count += 1,
+                Decision::Quit => {
+                    quitting = true;
+                    outcome.unreviewed_count += 1;
+                }
+            }
+
+            outcome.entries.push(entry);
+        }
+
+        Ok(outcome)
+    }
+
+    // Returns `None` at the end of input.
+    fn prompt(&mut self, prompt: &str) -> Result<Option<String>> {
+        match self.editor.readline(prompt) {
+            Ok(line) => Ok(Some(String::from(line.trim()))),
+            Err(ReadlineError::Eof | ReadlineError::Interrupted) => Ok(None),
+            Err(error) => Err(error.into()),
+        }
+    }
+
+    // Asks until a defined alias is given, returning it with its account, or
+    // `None` if nothing is given.
+    fn prompt_alias(&mut self, prompt: &str) -> Result<Option<(String, String)>> {
+        loop {
+            let Some(alias) = self.prompt(prompt)? else {
+                return Ok(None);
+            };
+            if alias.is_empty() {
+                return Ok(None);
+            }
+            if let Some(account) = resolve_account_alias(self.accounts, &alias) {
+                return Ok(Some((alias, account)));
+            }
+
+            let mut candidates: Vec<&String> = self
+                .accounts
+                .keys()
+                .filter(|candidate| candidate.starts_with(alias.as_str()))
+                .collect();
+            candidates.sort();
+            println!(
+                "Account alias '{}' is not defined. Matching aliases: {}",
+                alias,
+                candidates
+                    .iter()
+                    .map(|candidate| candidate.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+        }
+    }
+
+    // Replaces the category posting with several, each given as `alias amount`,
+    // where one may leave out its amount to take the remainder.
+    fn split(&mut self, entry: LedgerEntry) -> Result<LedgerEntry> {
+        println!("Enter one 'alias amount' per line, leaving out the amount on the last line for the remainder.");
+
+        let mut parts: Vec<(String, Option<f64>)> = Vec::new();
+        loop {
+            let Some(line) = self.prompt("Split: ")? else {
+                return Ok(entry);
+            };
+            if line.is_empty() {
+                println!("Split cancelled.");
+                return Ok(entry);
+            }
+
+            let (alias, amount_text) = line
+                .split_once(char::is_whitespace)
+                .map_or((line.as_str(), ""), |(alias, amount)| {
+                    (alias, amount.trim())
+                });
+            let Some(account) = resolve_account_alias(self.accounts, alias) else {
+                println!("Account alias '{}' is not defined.", alias);
+                continue;
+            };
+
+            if amount_text.is_empty() {
+                parts.push((account, None));
+                break;
+            }
+            match amount_text.parse::<f64>() {
+                Ok(amount) => parts.push((account, Some(amount))),
+                Err(_) => println!("Could not parse amount '{}'.", amount_text),
+            }
+        }
+
+        match split_category_posting(entry, &parts) {
+            Ok(split_entry) => Ok(split_entry),
+            Err((unchanged, error)) => {
+                println!("{}", error);
+                Ok(unchanged)
+            }
+        }
+    }
+
+    fn rule_for_decision(
+        &mut self,
+        entry: &LedgerEntry,
+        record: &SourceRecord,
+        alias: String,
+    ) -> Result<Option<TransactionRuleConfiguration>> {
+        match self.prompt("Save this as a new rule? [y/N]: ")? {
+            Some(answer) if answer.eq_ignore_ascii_case("y") => {}
+            _ => return Ok(None),
+        }
+
+        let tokens = description_tokens(&record.description);
+        if tokens.is_empty() {
+            println!("No words in the description to build a pattern from.");
+            return Ok(None);
+        }
+        let default_pattern = description_pattern(
+            &tokens[..tokens.len().min(RULE_PATTERN_TOKEN_COUNT)],
+            &[record.description.as_str()],
+        );
+
+        let pattern_string = loop {
+            let pattern = self
+                .prompt(&format!("Pattern [{}]: ", default_pattern))?
+                .filter(|pattern| !pattern.is_empty())
+                .unwrap_or_else(|| default_pattern.clone());
+
+            match Regex::new(&pattern) {
+                Ok(regex) if regex.is_match(&record.description) => break pattern,
+                Ok(_) => println!("Pattern does not match '{}'.", record.description),
+                Err(error) => println!("Invalid pattern: {}", error),
+            }
+        };
+
+        let fallback_rule = &self.matcher.fallback_rule;
+        Ok(Some(TransactionRuleConfiguration {
+            name: Some(entry.payee.clone()),
+            pattern_string,
+            account1: Some(fallback_rule.account1.clone()),
+            account2: alias,
+            payee: entry.payee.clone(),
+            needs_finalized: None,
+            negate_first_amount: fallback_rule.negate_first_amount.then_some(true),
+        }))
+    }
+}
+
+type EntryEditResult = std::result::Result<LedgerEntry, (LedgerEntry, color_eyre::Report)>;
+
+fn category_posting_index(entry: &LedgerEntry) -> Option<usize> {
+    entry
+        .lines
+        .iter()
+        .rposition(|line| matches!(line, EntryLine::Posting { .. }))
+}
+
+// Moves the category posting to another account, handing the entry back on failure.
+fn recategorize(mut entry: LedgerEntry, account_name: &str) -> EntryEditResult {
+    match category_posting_index(&entry) {
+        Some(index) => {
+            if let EntryLine::Posting { account, .. } = &mut entry.lines[index] {
+                *account = String::from(account_name);
+            }
+            Ok(entry)
+        }
+        None => Err((entry, eyre!("Entry has no postings to recategorize."))),
+    }
+}
+
+fn split_category_posting(entry: LedgerEntry, parts: &[(String, Option<f64>)]) -> EntryEditResult {
+    let Some(index) = category_posting_index(&entry) else {
+        return Err((entry, eyre!("Entry has no postings to split.")));
+    };
+    let EntryLine::Posting {
+        amount: category_amount,
+        price: None,
+        ..
+    } = &entry.lines[index]
+    else {
+        return Err((entry, eyre!("Postings with a price cannot be split.")));
+    };
+
+    // Amounts are entered without a sign and take the sign of the category posting
+    let category_amount = category_amount.clone();
+    let specified: f64 = parts.iter().filter_map(|(_, amount)| *amount).sum();
+    let remainder = category_amount.quantity.abs() - specified;
+
+    let mut lines: Vec<EntryLine> = Vec::new();
+    for (position, line) in entry.lines.iter().enumerate() {
+        if position != index {
+            lines.push(line.clone());
+            continue;
+        }
+
+        for (account, amount) in parts {
+            let quantity = amount.unwrap_or(remainder).abs();
+            lines.push(EntryLine::Posting {
+                account: account.clone(),
+                amount: Amount::new(
+                    quantity.copysign(category_amount.quantity),
+                    category_amount.commodity.clone(),
+                ),
+                price: None,
+            });
+        }
+    }
+
+    let builder = LedgerEntryBuilder {
+        date: entry.date,
+        status: entry.status,
+        code: entry.code.clone(),
+        payee: entry.payee.clone(),
+        lines,
+    };
+
+    builder.build().map_err(|error| (entry, error))
+}
@@ -0,0 +1,253 @@
+use super::{description_pattern, description_tokens};
+use crate::ledger_entry::{EntryLine, Journal, LedgerEntry};
+use crate::transaction_matcher::definitions::{
+    alias_for_account, AccountMap, TransactionRuleConfiguration,
+};
+use crate::transaction_matcher::matcher::source_description;
+use log::trace;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use voca_rs::case;
+
+// Entries are grouped by this many leading words of their source description or payee.
+static LEARNING_KEY_TOKEN_COUNT: usize = 2;
+
+pub struct LearningOptions {
+    // Postings to accounts starting with one of these are the categories rules are learned for.
+    pub category_account_prefixes: Vec<String>,
+    pub min_occurrences: usize,
+    pub min_confidence: f64,
+}
+
+pub struct LearnedRule {
+    // The share of the entries for the rule that went to its account.
+    pub confidence: f64,
+    pub occurrences: usize,
+    pub rule: TransactionRuleConfiguration,
+}
+
+// The same source description or payee going to different accounts.
+pub struct LearningConflict {
+    pub key: String,
+    // Account names and how many entries went to each, most common first.
+    pub accounts: Vec<(String, usize)>,
+}
+
+pub struct LearnedRules {
+    pub rules: Vec<LearnedRule>,
+    pub conflicts: Vec<LearningConflict>,
+    // Aliases generated for accounts that were not in the account map.
+    pub new_accounts: BTreeMap<String, String>,
+}
+
+#[derive(Default)]
+struct KeyObservations<'a> {
+    descriptions: Vec<&'a str>,
+    payees: BTreeMap<&'a str, usize>,
+    category_accounts: BTreeMap<&'a str, usize>,
+    statement_accounts: BTreeMap<&'a str, usize>,
+}
+
+pub fn learn_rules(
+    journal: &Journal,
+    accounts: &AccountMap,
+    options: &LearningOptions,
+) -> LearnedRules {
+    let mut observations: BTreeMap<Vec<String>, KeyObservations> = BTreeMap::new();
+
+    for entry in &journal.entries {
+        let Some(CategorizedEntry {
+            description,
+            category_account,
+            statement_account,
+            ..
+        }) = categorized_entry(entry, &options.category_account_prefixes)
+        else {
+            continue;
+        };
+
+        let tokens = description_tokens(description);
+        if tokens.is_empty() {
+            continue;
+        }
+
+        let key = tokens[..tokens.len().min(LEARNING_KEY_TOKEN_COUNT)].to_vec();
+        let observed = observations.entry(key).or_default();
+        observed.descriptions.push(description);
+        *observed.payees.entry(entry.payee.as_str()).or_default() += 1;
+        *observed
+            .category_accounts
+            .entry(category_account)
+            .or_default() += 1;
+        *observed
+            .statement_accounts
+            .entry(statement_account)
+            .or_default() += 1;
+    }
+
+    trace!(
+        "Grouped {} journal entries into {} descriptions.",
+        journal.entries.len(),
+        observations.len()
+    );
+
+    let mut aliases = AliasGenerator::new(accounts);
+    let mut rules = Vec::new();
+    let mut conflicts = Vec::new();
+
+    for (key, observed) in &observations {
+        let occurrences = observed.descriptions.len();
+        let category_accounts = most_common_first(&observed.category_accounts);
+        let (category_account, category_count) = category_accounts[0];
+
+        if category_accounts.len() > 1 {
+            conflicts.push(LearningConflict {
+                key: key.join(" "),
+                accounts: category_accounts
+                    .iter()
+                    .map(|(account, count)| (String::from(*account), *count))
+                    .collect(),
+            });
+        }
+
+        let confidence = category_count as f64 / occurrences as f64;
+        if occurrences < options.min_occurrences || confidence < options.min_confidence {
+            continue;
+        }
+
+        let (statement_account, _) = most_common_first(&observed.statement_accounts)[0];
+        let (payee, _) = most_common_first(&observed.payees)[0];
+
+        rules.push(LearnedRule {
+            confidence,
+            occurrences,
+            rule: TransactionRuleConfiguration {
+                name: Some(case::title_case(&key.join(" "))),
+                pattern_string: description_pattern(key, &observed.descriptions),
+                account1: Some(aliases.alias_for(statement_account)),
+                account2: aliases.alias_for(category_account),
+                payee: String::from(payee),
+                needs_finalized: None,
+                negate_first_amount: None,
+            },
+        });
+    }
+
+    rules.sort_by_key(|learned| Reverse(learned.occurrences));
+
+    LearnedRules {
+        rules,
+        conflicts,
+        new_accounts: aliases.new_accounts,
+    }
+}
+
+// An entry with a single posting to a category account.
+pub struct CategorizedEntry<'a> {
+    // The `SOURCE:` comment of the entry, or its payee when there is none.
+    pub description: &'a str,
+    pub category_account: &'a str,
+    pub category_amount: f64,
+    // The account the category posting was balanced against.
+    pub statement_account: &'a str,
+}
+
+#[must_use]
+pub fn categorized_entry<'a>(
+    entry: &'a LedgerEntry,
+    category_account_prefixes: &[String],
+) -> Option<CategorizedEntry<'a>> {
+    let postings: Vec<(&str, f64)> = entry
+        .lines
+        .iter()
+        .filter_map(|line| match line {
+            EntryLine::Posting {
+                account, amount, ..
+            } => Some((account.as_str(), amount.quantity)),
+            EntryLine::Comment { .. } => None,
+        })
+        .collect();
+
+    let (categories, others): (Vec<_>, Vec<_>) = postings.iter().partition(|(account, _)| {
+        category_account_prefixes
+            .iter()
+            .any(|prefix| account.starts_with(prefix.as_str()))
+    });
+
+    match (categories.as_slice(), others.first()) {
+        ([(category_account, category_amount)], Some((statement_account, _))) => {
+            Some(CategorizedEntry {
+                description: source_description(entry).unwrap_or(entry.payee.as_str()),
+                category_account,
+                category_amount: *category_amount,
+                statement_account,
+            })
+        }
+        _ => None,
+    }
+}
+
+fn most_common_first<'a>(counts: &BTreeMap<&'a str, usize>) -> Vec<(&'a str, usize)> {
+    let mut counts: Vec<(&str, usize)> = counts.iter().map(|(key, count)| (*key, *count)).collect();
+    counts.sort_by_key(|(_, count)| Reverse(*count));
+    counts
+}
+
+// Reuses existing aliases, including those through wildcard aliases, and
+// otherwise derives one from the account name, such as `food.groceries` for
+// `Expenses:Food:Groceries`.
+struct AliasGenerator<'a> {
+    accounts: &'a AccountMap,
+    new_accounts: BTreeMap<String, String>,
+}
+
+impl<'a> AliasGenerator<'a> {
+    fn new(accounts: &'a AccountMap) -> Self {
+        AliasGenerator {
+            accounts,
+            new_accounts: BTreeMap::new(),
+        }
+    }
+
+    fn alias_for(&mut self, account: &str) -> String {
+        if let Some(alias) = alias_for_account(self.accounts, account) {
+            return alias;
+        }
+        if let Some((alias, _)) = self
+            .new_accounts
+            .iter()
+            .find(|(_, new_account)| new_account.as_str() == account)
+        {
+            return alias.clone();
+        }
+
+        let components: Vec<&str> = account.split(':').collect();
+        // The top level, like `Expenses`, is left out unless it is all there is
+        let significant = if components.len() > 1 {
+            &components[1..]
+        } else {
+            &components[..]
+        };
+        let base_alias = significant
+            .iter()
+            .map(|component| case::snake_case(component))
+            .collect::<Vec<String>>()
+            .join(".");
+
+        let taken: BTreeSet<&String> = self
+            .accounts
+            .keys()
+            .chain(self.new_accounts.keys())
+            .collect();
+        let mut alias = base_alias.clone();
+        let mut suffix = 2;
+        while taken.contains(&alias) {
+            alias = format!("{}{}", base_alias, suffix);
+            suffix += 1;
+        }
+
+        self.new_accounts
+            .insert(alias.clone(), String::from(account));
+        alias
+    }
+}
@@ -0,0 +1,79 @@
+pub mod learn;
+pub mod suggest;
+
+pub use learn::{learn_rules, LearnedRules, LearningOptions};
+pub use suggest::{suggest_rules, RuleSuggestion};
+
+use crate::transaction_matcher::definitions::TransactionRuleConfiguration;
+use color_eyre::eyre::Result;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+// Splits a description into its upper-cased words, leaving out anything with
+// a digit in it, such as store numbers, dates and reference IDs.
+#[must_use]
+pub fn description_tokens(description: &str) -> Vec<String> {
+    description
+        .split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '&')
+        .filter(|token| !token.is_empty() && !token.chars().any(|c| c.is_ascii_digit()))
+        .map(str::to_uppercase)
+        .collect()
+}
+
+// A case-insensitive pattern capturing the given words, anchored to the start
+// of the description when every one of the descriptions starts with them.
+#[must_use]
+pub fn description_pattern(tokens: &[String], descriptions: &[&str]) -> String {
+    let words: Vec<String> = tokens.iter().map(|token| regex::escape(token)).collect();
+    let words_pattern = words.join(r"\W+");
+
+    let anchored = descriptions.iter().all(|description| {
+        description
+            .trim_start()
+            .to_uppercase()
+            .starts_with(tokens[0].as_str())
+    });
+
+    if anchored {
+        format!(r"(?i)^\s*({})\b", words_pattern)
+    } else {
+        format!(r"(?i)\b({})\b", words_pattern)
+    }
+}
+
+#[derive(Serialize)]
+struct RulesSnippet<'a> {
+    import_file_definitions: BTreeMap<&'a str, FormatRulesSnippet<'a>>,
+}
+
+#[derive(Serialize)]
+struct FormatRulesSnippet<'a> {
+    transaction_rules: Vec<&'a TransactionRuleConfiguration>,
+}
+
+// Renders a rule as a TOML snippet that can be pasted into the configuration file.
+pub fn transaction_rule_toml(
+    format_name: &str,
+    rule: &TransactionRuleConfiguration,
+) -> Result<String> {
+    let snippet = RulesSnippet {
+        import_file_definitions: BTreeMap::from([(
+            format_name,
+            FormatRulesSnippet {
+                transaction_rules: vec![rule],
+            },
+        )]),
+    };
+
+    Ok(toml::to_string(&snippet)?)
+}
+
+#[derive(Serialize)]
+struct AccountsSnippet<'a> {
+    accounts: &'a BTreeMap<String, String>,
+}
+
+// Renders account aliases as an `[accounts]` TOML snippet.
+pub fn accounts_toml(accounts: &BTreeMap<String, String>) -> Result<String> {
+    Ok(toml::to_string(&AccountsSnippet { accounts })?)
+}
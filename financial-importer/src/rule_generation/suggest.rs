@@ -0,0 +1,122 @@
+use super::{description_pattern, description_tokens};
+use crate::source_record::SourceRecord;
+use crate::transaction_matcher::definitions::{TransactionMatcher, TransactionRuleConfiguration};
+use color_eyre::eyre::Result;
+use log::trace;
+use regex::Regex;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use voca_rs::case;
+
+// Records are first grouped by this many leading words of their descriptions.
+static CLUSTER_KEY_TOKEN_COUNT: usize = 2;
+// Written in place of the account alias that has to be filled in by hand.
+pub static ACCOUNT_PLACEHOLDER: &str = "TODO";
+static SUGGESTED_PAYEE_TEMPLATE: &str = "$1";
+
+pub struct RuleSuggestion {
+    pub record_count: usize,
+    // The distinct descriptions of the records the rule would match.
+    pub descriptions: Vec<String>,
+    pub rule: TransactionRuleConfiguration,
+}
+
+// Groups the descriptions of unmatched records by their common leading words,
+// proposing a rule for every group of at least `min_records` records.
+pub fn suggest_rules(
+    records: &[SourceRecord],
+    matcher: &TransactionMatcher,
+    min_records: usize,
+) -> Result<Vec<RuleSuggestion>> {
+    let mut clusters: BTreeMap<Vec<String>, Vec<&SourceRecord>> = BTreeMap::new();
+
+    // Records an existing rule already matches need no new one
+    for record in records
+        .iter()
+        .filter(|record| !matcher.rule_patterns.is_match(&record.description))
+    {
+        let tokens = description_tokens(&record.description);
+        if tokens.is_empty() {
+            trace!(
+                "No words left to cluster description '{}' on.",
+                record.description
+            );
+            continue;
+        }
+
+        let key = tokens[..tokens.len().min(CLUSTER_KEY_TOKEN_COUNT)].to_vec();
+        clusters.entry(key).or_default().push(record);
+    }
+
+    let mut suggestions = Vec::new();
+
+    for cluster in clusters
+        .values()
+        .filter(|cluster| cluster.len() >= min_records)
+    {
+        let common_tokens = common_leading_tokens(cluster);
+        let descriptions: Vec<&str> = cluster
+            .iter()
+            .map(|record| record.description.as_str())
+            .collect();
+        let pattern_string = description_pattern(&common_tokens, &descriptions);
+
+        // Make sure the suggestion really covers the whole cluster
+        let pattern = Regex::new(&pattern_string)?;
+        if !cluster
+            .iter()
+            .all(|record| pattern.is_match(&record.description))
+        {
+            trace!(
+                "Pattern '{}' does not match all of its cluster, skipping.",
+                pattern_string
+            );
+            continue;
+        }
+
+        let mut descriptions: Vec<String> = cluster
+            .iter()
+            .map(|record| record.description.clone())
+            .collect();
+        descriptions.sort();
+        descriptions.dedup();
+
+        suggestions.push(RuleSuggestion {
+            record_count: cluster.len(),
+            descriptions,
+            rule: TransactionRuleConfiguration {
+                name: Some(case::title_case(&common_tokens.join(" "))),
+                pattern_string,
+                account1: Some(matcher.fallback_rule.account1.clone()),
+                account2: String::from(ACCOUNT_PLACEHOLDER),
+                payee: String::from(SUGGESTED_PAYEE_TEMPLATE),
+                needs_finalized: None,
+                negate_first_amount: matcher.fallback_rule.negate_first_amount.then_some(true),
+            },
+        });
+    }
+
+    // Largest clusters first, as those save the most work
+    suggestions.sort_by_key(|suggestion| Reverse(suggestion.record_count));
+
+    Ok(suggestions)
+}
+
+fn common_leading_tokens(cluster: &[&SourceRecord]) -> Vec<String> {
+    let token_lists: Vec<Vec<String>> = cluster
+        .iter()
+        .map(|record| description_tokens(&record.description))
+        .collect();
+
+    let mut common = token_lists[0].clone();
+    for tokens in &token_lists[1..] {
+        let shared = common
+            .iter()
+            .zip(tokens)
+            .take_while(|(a, b)| a == b)
+            .count();
+        common.truncate(shared);
+    }
+
+    common
+}
@@ -1,14 +1,30 @@
+use crate::app::Failure;
+use crate::ofx;
 use chrono::NaiveDate;
-use color_eyre::eyre::Result;
+use color_eyre::eyre::{eyre, Error, Result, WrapErr};
 use log::{info, trace};
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
 use std::path::Path;
+use std::str::FromStr;
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SourceRecord {
     pub date: NaiveDate,
     pub description: String,
     pub amount: f64,
+    // For purchases abroad, the amount and currency before conversion, as given by the statement.
+    #[serde(default)]
+    pub original_amount: Option<f64>,
+    #[serde(default)]
+    pub original_currency: Option<String>,
+    // The statement's own unique ID for the transaction, such as the FITID of an OFX statement.
+    #[serde(default)]
+    pub fitid: Option<String>,
+    // The line in the input file the record was loaded from, if known.
+    #[serde(skip)]
+    pub line_number: Option<u64>,
 }
 
 impl Ord for SourceRecord {
@@ -31,40 +47,346 @@ impl PartialEq for SourceRecord {
 
 impl Eq for SourceRecord {}
 
-pub fn load_source_records(input_path: &Path) -> Result<Vec<SourceRecord>> {
-    trace!(
-        "Processing CSV using input file '{}'.",
-        input_path.to_str().unwrap()
+impl SourceRecord {
+    // Stricter than `Eq`, which ignores the amount so that records sort by date and description.
+    #[must_use]
+    pub fn is_duplicate_of(&self, other: &Self) -> bool {
+        self.date == other.date
+            && self.description == other.description
+            && self.amount == other.amount
+    }
+}
+
+#[derive(Debug)]
+pub struct RejectedSourceRecord {
+    pub line_number: Option<u64>,
+    pub reason: String,
+    // The raw fields of the row, when the row itself could be read.
+    pub fields: Vec<String>,
+}
+
+impl fmt::Display for RejectedSourceRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.line_number {
+            Some(line_number) => write!(f, "Line {}: {}", line_number, self.reason),
+            None => write!(f, "Unknown line: {}", self.reason),
+        }
+    }
+}
+
+// A record repeating the date, description and amount of an earlier record.
+#[derive(Debug)]
+pub struct SuspectedDuplicate {
+    pub line_number: Option<u64>,
+    pub earlier_line_number: Option<u64>,
+    pub date: NaiveDate,
+    pub description: String,
+    pub amount: f64,
+}
+
+impl fmt::Display for SuspectedDuplicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} repeats {}: {} {} {}",
+            describe_line_number(self.line_number),
+            describe_line_number(self.earlier_line_number),
+            self.date,
+            self.description,
+            self.amount
+        )
+    }
+}
+
+pub struct LoadedSourceRecords {
+    pub headers: Vec<String>,
+    pub records: Vec<SourceRecord>,
+    pub rejected: Vec<RejectedSourceRecord>,
+    // The records suspected to be duplicates of earlier ones, for the caller
+    // to report, whether the policy kept or dropped them.
+    pub duplicates: Vec<SuspectedDuplicate>,
+}
+
+// What to do with rows of an input file that fail to deserialize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseErrorPolicy {
+    // Abort the import on the first run with any bad rows.
+    Strict,
+    // Write the bad rows, with line numbers and reasons, to a rejects file.
+    Quarantine,
+    // Report the bad rows and continue without them.
+    Lenient,
+}
+
+impl FromStr for ParseErrorPolicy {
+    type Err = Error;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "strict" => Ok(ParseErrorPolicy::Strict),
+            "quarantine" => Ok(ParseErrorPolicy::Quarantine),
+            "lenient" => Ok(ParseErrorPolicy::Lenient),
+            _ => Err(eyre!(
+                "Unknown parse error policy '{}', expected one of: strict, quarantine, lenient.",
+                policy
+            )),
+        }
+    }
+}
+
+// What to do with records repeating the date, description and amount of an
+// earlier record of the same file, as when rows are repeated across pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DuplicatePolicy {
+    // List the suspected duplicates and keep them.
+    Warn,
+    // List the suspected duplicates and leave them out.
+    Drop,
+    // Keep every record without checking.
+    Keep,
+}
+
+impl FromStr for DuplicatePolicy {
+    type Err = Error;
+
+    fn from_str(policy: &str) -> Result<Self, Self::Err> {
+        match policy {
+            "warn" => Ok(DuplicatePolicy::Warn),
+            "drop" => Ok(DuplicatePolicy::Drop),
+            "keep" => Ok(DuplicatePolicy::Keep),
+            _ => Err(eyre!(
+                "Unknown duplicate policy '{}', expected one of: warn, drop, keep.",
+                policy
+            )),
+        }
+    }
+}
+
+// How the rows of an input file that are bad or repeated are handled.
+#[derive(Debug, Clone, Copy)]
+pub struct LoadPolicies {
+    pub parse_errors: ParseErrorPolicy,
+    pub duplicates: DuplicatePolicy,
+}
+
+pub fn load_source_records(
+    input_path: &Path,
+    policies: LoadPolicies,
+    rejects_path: &Path,
+) -> Result<LoadedSourceRecords> {
+    let mut loaded = read_source_records(input_path)?;
+    apply_duplicate_policy(&mut loaded, policies.duplicates);
+
+    if loaded.rejected.is_empty() {
+        return Ok(loaded);
+    }
+
+    info!(
+        "Encountered {} errors in loading source records",
+        loaded.rejected.len()
     );
-    let mut reader = csv::Reader::from_path(input_path)?;
-    let (rows, errors): (Vec<_>, Vec<_>) = reader
-        .deserialize::<SourceRecord>()
-        .partition(Result::is_ok);
 
-    let rows: Vec<SourceRecord> = rows.into_iter().map(Result::unwrap).collect();
+    match policies.parse_errors {
+        ParseErrorPolicy::Strict => loaded
+            .rejected
+            .iter()
+            .map(ToString::to_string)
+            .fold(
+                Err(eyre!(
+                    "{} rows of '{}' failed to parse:",
+                    loaded.rejected.len(),
+                    input_path.to_str().unwrap()
+                )),
+                color_eyre::Help::section,
+            )
+            .wrap_err(Failure::ParseErrors),
+        ParseErrorPolicy::Quarantine => {
+            write_rejected_records(rejects_path, &loaded.headers, &loaded.rejected)?;
+            Ok(loaded)
+        }
+        ParseErrorPolicy::Lenient => {
+            for rejected_record in &loaded.rejected {
+                eprintln!("Got error: {}", rejected_record);
+            }
+            Ok(loaded)
+        }
+    }
+}
+
+fn apply_duplicate_policy(loaded: &mut LoadedSourceRecords, policy: DuplicatePolicy) {
+    if policy == DuplicatePolicy::Keep {
+        return;
+    }
 
-    trace!("Successfully loaded {} source records.", rows.len());
+    let duplicate_pairs = duplicate_record_indexes(&loaded.records);
+    loaded.duplicates = duplicate_pairs
+        .iter()
+        .map(|&(earlier_index, index)| {
+            let (earlier, record) = (&loaded.records[earlier_index], &loaded.records[index]);
+            SuspectedDuplicate {
+                line_number: record.line_number,
+                earlier_line_number: earlier.line_number,
+                date: record.date,
+                description: record.description.clone(),
+                amount: record.amount,
+            }
+        })
+        .collect();
 
-    if !errors.is_empty() {
+    if policy == DuplicatePolicy::Drop && !duplicate_pairs.is_empty() {
         info!(
-            "Encountered {} errors in loading source records",
-            errors.len()
+            "Dropping {} duplicate source records",
+            duplicate_pairs.len()
+        );
+        let duplicate_indexes: HashSet<usize> = duplicate_pairs
+            .into_iter()
+            .map(|(_, index)| index)
+            .collect();
+        let mut index = 0;
+        loaded.records.retain(|_| {
+            let keep = !duplicate_indexes.contains(&index);
+            index += 1;
+            keep
+        });
+    }
+}
+
+pub fn describe_line_number(line_number: Option<u64>) -> String {
+    match line_number {
+        Some(line_number) => format!("line {}", line_number),
+        None => String::from("unknown line"),
+    }
+}
+
+// Reads all rows of the input file, a CSV file or an OFX statement, keeping
+// the rows that failed to deserialize alongside the successfully loaded records.
+pub fn read_source_records(input_path: &Path) -> Result<LoadedSourceRecords> {
+    let contents = std::fs::read(input_path).wrap_err_with(|| {
+        format!(
+            "Encountered errors reading input file '{}'.",
+            input_path.to_str().unwrap()
+        )
+    })?;
+    let text = String::from_utf8_lossy(&contents);
+    if ofx::is_ofx(&text) {
+        trace!(
+            "Processing OFX using input file '{}'.",
+            input_path.to_str().unwrap()
         );
+        return Ok(ofx::read_ofx_records(&text));
+    }
+
+    trace!(
+        "Processing CSV using input file '{}'.",
+        input_path.to_str().unwrap()
+    );
+    let mut reader = csv::Reader::from_reader(contents.as_slice());
+    let headers = reader.headers()?.clone();
+
+    let mut records: Vec<SourceRecord> = Vec::new();
+    let mut rejected: Vec<RejectedSourceRecord> = Vec::new();
+
+    for result in reader.records() {
+        let string_record = match result {
+            Ok(string_record) => string_record,
+            Err(error) => {
+                rejected.push(RejectedSourceRecord {
+                    line_number: error.position().map(csv::Position::line),
+                    reason: error.to_string(),
+                    fields: Vec::new(),
+                });
+                continue;
+            }
+        };
 
-        for error in errors {
-            let error = error.unwrap_err();
-            eprintln!("Got error: {:?}", error.kind());
-            if let Some(position) = error.position() {
-                eprintln!(
-                    "\tError at Line: {}, Byte: {}",
-                    position.line(),
-                    position.byte()
-                );
+        let line_number = string_record.position().map(csv::Position::line);
+        match string_record.deserialize::<SourceRecord>(Some(&headers)) {
+            Ok(mut record) => {
+                record.line_number = line_number;
+                records.push(record);
             }
+            Err(error) => rejected.push(RejectedSourceRecord {
+                line_number,
+                reason: error.to_string(),
+                fields: string_record.iter().map(String::from).collect(),
+            }),
         }
     }
 
-    Ok(rows)
+    trace!("Successfully loaded {} source records.", records.len());
+
+    Ok(LoadedSourceRecords {
+        headers: headers.iter().map(String::from).collect(),
+        records,
+        rejected,
+        duplicates: Vec::new(),
+    })
+}
+
+static REJECTS_LINE_NUMBER_HEADER: &str = "line_number";
+static REJECTS_REASON_HEADER: &str = "error";
+
+pub fn write_rejected_records(
+    output_path: &Path,
+    headers: &[String],
+    rejected: &[RejectedSourceRecord],
+) -> Result<()> {
+    trace!(
+        "Writing rejected source records to '{}'.",
+        output_path.to_str().unwrap()
+    );
+
+    let mut writer = csv::WriterBuilder::new()
+        .flexible(true)
+        .from_path(output_path)?;
+
+    writer.write_record(
+        [REJECTS_LINE_NUMBER_HEADER, REJECTS_REASON_HEADER]
+            .into_iter()
+            .chain(headers.iter().map(String::as_str)),
+    )?;
+
+    for rejected_record in rejected {
+        let line_number = rejected_record
+            .line_number
+            .map_or_else(String::new, |line_number| line_number.to_string());
+
+        writer.write_record(
+            [line_number.as_str(), rejected_record.reason.as_str()]
+                .into_iter()
+                .chain(rejected_record.fields.iter().map(String::as_str)),
+        )?;
+    }
+
+    trace!(
+        "Successfully wrote {} rejected records to the CSV file.",
+        rejected.len()
+    );
+
+    Ok(())
+}
+
+// Returns pairs of (first occurrence, later duplicate) for records sharing date, description and amount.
+#[must_use]
+pub fn find_duplicate_records(records: &[SourceRecord]) -> Vec<(&SourceRecord, &SourceRecord)> {
+    duplicate_record_indexes(records)
+        .into_iter()
+        .map(|(earlier_index, index)| (&records[earlier_index], &records[index]))
+        .collect()
+}
+
+// The indexes of the pairs returned by `find_duplicate_records`.
+fn duplicate_record_indexes(records: &[SourceRecord]) -> Vec<(usize, usize)> {
+    records
+        .iter()
+        .enumerate()
+        .filter_map(|(index, record)| {
+            records[..index]
+                .iter()
+                .position(|earlier| earlier.is_duplicate_of(record))
+                .map(|earlier_index| (earlier_index, index))
+        })
+        .collect()
 }
 
 pub fn write_source_records(output_path: &Path, source_records: &[&SourceRecord]) -> Result<()> {
@@ -1,16 +1,58 @@
-use crate::app::{APP_NAME, DEFAULT_CONFIG_FILE_NAME};
-use crate::transaction_matcher::definitions::FinancialImporter;
+use crate::app::{APP_NAME, CONFIG_INCLUDE_DIRECTORY_NAME, DEFAULT_CONFIG_FILE_NAME};
+use crate::ledger_entry::{read_journal, LedgerFormat};
+use crate::transaction_matcher::definitions::{
+    AccountMap, FinancialImporter, FinancialImporterConfiguration, RuleSetConfiguration,
+    RuleSetMap, TransactionMatcherConfiguration,
+};
+use crate::transfers::TransferDetection;
 use color_eyre::eyre::{eyre, Result, WrapErr};
 use log::{info, trace};
 use platform_dirs::AppDirs;
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
 use std::path::{Path, PathBuf};
 
-pub fn load_configuration(config_file: Option<PathBuf>) -> Result<FinancialImporter> {
-    let config_pathbuf: PathBuf = if let Some(config_pathname) = config_file {
-        config_pathname
+// A configuration file and its contents, as the main file or one it includes.
+pub struct ConfigurationFile {
+    pub path: PathBuf,
+    pub contents: String,
+}
+
+#[derive(Deserialize)]
+struct ConfigurationFileIncludes {
+    #[serde(default)]
+    include: Vec<PathBuf>,
+}
+
+// The parts of a configuration file before they are merged with those of
+// the other files. Import file definitions are kept as TOML so that errors
+// in them can be reported with the file they came from.
+#[derive(Deserialize)]
+struct PartialConfiguration {
+    #[serde(default)]
+    accounts: AccountMap,
+    #[serde(default)]
+    import_file_definitions: HashMap<String, toml::Value>,
+    #[serde(default)]
+    rule_sets: HashMap<String, toml::Value>,
+    formatting: Option<LedgerFormat>,
+    // A Ledger journal whose `account` directives declare every valid account.
+    accounts_journal: Option<PathBuf>,
+    transfer_detection: Option<TransferDetection>,
+}
+
+// The given configuration file, or the default one in the platform's config directory.
+pub fn config_file_path(config_file: Option<PathBuf>) -> Result<PathBuf> {
+    if let Some(config_pathname) = config_file {
+        Ok(config_pathname)
     } else {
-        default_config_filename().ok_or_else(|| eyre!("Problems getting app config file path"))?
-    };
+        default_config_filename().ok_or_else(|| eyre!("Problems getting app config file path"))
+    }
+}
+
+pub fn load_configuration(config_file: Option<PathBuf>) -> Result<FinancialImporter> {
+    let config_pathbuf: PathBuf = config_file_path(config_file)?;
     let config_pathname: &Path = config_pathbuf.as_path();
 
     info!(
@@ -18,19 +60,280 @@ pub fn load_configuration(config_file: Option<PathBuf>) -> Result<FinancialImpor
         config_pathname.to_str().unwrap()
     );
 
-    let contents = std::fs::read_to_string(config_pathname).wrap_err_with(|| {
-        format!(
-            "Encountered errors reading config file '{}'.",
-            config_pathname.to_str().unwrap()
-        )
-    })?;
+    let files = read_configuration_files(config_pathname)?;
 
     trace!("Starting configuration file validation.");
 
-    let importer: FinancialImporter = toml::from_str(&contents)?;
+    configuration_from_files(&files)
+}
+
+// Reads the configuration file, then the files it lists in `include` and the
+// `conf.d` directory next to it, and so on for the files those include.
+pub fn read_configuration_files(config_file: &Path) -> Result<Vec<ConfigurationFile>> {
+    let mut reader = ConfigurationReader::default();
+    reader.read_file(config_file)?;
+
+    let include_directory = config_file
+        .parent()
+        .unwrap_or_else(|| Path::new(""))
+        .join(CONFIG_INCLUDE_DIRECTORY_NAME);
+    if include_directory.is_dir() {
+        let mut include_paths: Vec<PathBuf> = std::fs::read_dir(&include_directory)
+            .wrap_err_with(|| {
+                format!(
+                    "Encountered errors reading config directory '{}'.",
+                    include_directory.to_str().unwrap()
+                )
+            })?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<std::io::Result<_>>()?;
+        include_paths.retain(|path| {
+            path.extension()
+                .is_some_and(|extension| extension == "toml")
+        });
+        include_paths.sort();
+
+        for include_path in include_paths {
+            reader.read_file(&include_path)?;
+        }
+    }
+
+    trace!("Read {} configuration files.", reader.files.len());
+
+    Ok(reader.files)
+}
+
+#[derive(Default)]
+struct ConfigurationReader {
+    files: Vec<ConfigurationFile>,
+    // Files already read, so that a file included twice is only read once.
+    read_paths: HashSet<PathBuf>,
+    // The files currently being read, to catch files that include themselves.
+    include_stack: Vec<PathBuf>,
+}
+
+impl ConfigurationReader {
+    fn read_file(&mut self, path: &Path) -> Result<()> {
+        let canonical_path = path.canonicalize().wrap_err_with(|| {
+            format!(
+                "Encountered errors reading config file '{}'.",
+                path.to_str().unwrap()
+            )
+        })?;
+
+        if self.include_stack.contains(&canonical_path) {
+            return Err(eyre!(
+                "Config file '{}' includes itself.",
+                path.to_str().unwrap()
+            ));
+        }
+        if !self.read_paths.insert(canonical_path.clone()) {
+            trace!(
+                "Skipping config file '{}', which was already read.",
+                path.to_str().unwrap()
+            );
+            return Ok(());
+        }
+
+        let contents = std::fs::read_to_string(path).wrap_err_with(|| {
+            format!(
+                "Encountered errors reading config file '{}'.",
+                path.to_str().unwrap()
+            )
+        })?;
+
+        let ConfigurationFileIncludes { include } =
+            toml::from_str(&contents).wrap_err_with(|| describe_file_error(path))?;
+
+        self.files.push(ConfigurationFile {
+            path: path.to_path_buf(),
+            contents,
+        });
+
+        self.include_stack.push(canonical_path);
+        for include_path in include {
+            let include_path = path
+                .parent()
+                .unwrap_or_else(|| Path::new(""))
+                .join(include_path);
+            self.read_file(&include_path)?;
+        }
+        self.include_stack.pop();
+
+        Ok(())
+    }
+}
+
+// Merges the accounts, import file definitions and rule sets of the files,
+// which must each be defined only once, and validates the result.
+pub fn configuration_from_files(files: &[ConfigurationFile]) -> Result<FinancialImporter> {
+    let mut accounts = AccountMap::new();
+    let mut import_file_definitions: HashMap<String, TransactionMatcherConfiguration> =
+        HashMap::new();
+    let mut rule_sets = RuleSetMap::new();
+    let mut formatting: Option<LedgerFormat> = None;
+    let mut account_files: HashMap<String, &Path> = HashMap::new();
+    let mut definition_files: HashMap<String, PathBuf> = HashMap::new();
+    let mut formatting_file: Option<&Path> = None;
+    let mut accounts_journal: Option<(PathBuf, &Path)> = None;
+    let mut transfer_detection: Option<(TransferDetection, &Path)> = None;
+    let mut conflicts = Vec::new();
+
+    for ConfigurationFile { path, contents } in files {
+        let partial: PartialConfiguration =
+            toml::from_str(contents).wrap_err_with(|| describe_file_error(path))?;
+
+        for (alias, account) in partial.accounts {
+            if let Some(other_path) = account_files.get(&alias) {
+                conflicts.push(eyre!(
+                    "Account alias '{}' is defined in both '{}' and '{}'.",
+                    alias,
+                    other_path.to_str().unwrap(),
+                    path.to_str().unwrap()
+                ));
+                continue;
+            }
+            account_files.insert(alias.clone(), path);
+            accounts.insert(alias, account);
+        }
+
+        for (format_name, definition) in partial.import_file_definitions {
+            if let Some(other_path) = definition_files.get(&format_name) {
+                conflicts.push(eyre!(
+                    "File format definition '{}' is defined in both '{}' and '{}'.",
+                    format_name,
+                    other_path.to_str().unwrap(),
+                    path.to_str().unwrap()
+                ));
+                continue;
+            }
+
+            let configuration = TransactionMatcherConfiguration::deserialize(definition)
+                .wrap_err_with(|| {
+                    format!(
+                        "Error in file format definition '{}' of config file '{}'.",
+                        format_name,
+                        path.to_str().unwrap()
+                    )
+                })?;
+            definition_files.insert(format_name.clone(), path.clone());
+            import_file_definitions.insert(format_name, configuration);
+        }
+
+        for (rule_set_name, rule_set) in partial.rule_sets {
+            if let Some(other_path) = rule_sets
+                .get(&rule_set_name)
+                .and_then(|other: &RuleSetConfiguration| other.source_file.as_ref())
+            {
+                conflicts.push(eyre!(
+                    "Rule set '{}' is defined in both '{}' and '{}'.",
+                    rule_set_name,
+                    other_path.to_str().unwrap(),
+                    path.to_str().unwrap()
+                ));
+                continue;
+            }
+
+            let mut rule_set = RuleSetConfiguration::deserialize(rule_set).wrap_err_with(|| {
+                format!(
+                    "Error in rule set '{}' of config file '{}'.",
+                    rule_set_name,
+                    path.to_str().unwrap()
+                )
+            })?;
+            rule_set.source_file = Some(path.clone());
+            rule_sets.insert(rule_set_name, rule_set);
+        }
+
+        // A conflict is recorded and the rest of the file still checked, to report them all
+        if let Some(file_formatting) = partial.formatting {
+            if let Some(other_path) = &formatting_file {
+                conflicts.push(eyre!(
+                    "Formatting is defined in both '{}' and '{}'.",
+                    other_path.to_str().unwrap(),
+                    path.to_str().unwrap()
+                ));
+            } else {
+                formatting_file = Some(path);
+                formatting = Some(file_formatting);
+            }
+        }
+
+        if let Some(journal_path) = partial.accounts_journal {
+            if let Some((_, other_path)) = &accounts_journal {
+                conflicts.push(eyre!(
+                    "The accounts journal is defined in both '{}' and '{}'.",
+                    other_path.to_str().unwrap(),
+                    path.to_str().unwrap()
+                ));
+            } else {
+                // Relative to the config file it is defined in
+                let journal_path = path
+                    .parent()
+                    .unwrap_or_else(|| Path::new(""))
+                    .join(journal_path);
+                accounts_journal = Some((journal_path, path));
+            }
+        }
+
+        if let Some(file_transfer_detection) = partial.transfer_detection {
+            if let Some((_, other_path)) = &transfer_detection {
+                conflicts.push(eyre!(
+                    "Transfer detection is defined in both '{}' and '{}'.",
+                    other_path.to_str().unwrap(),
+                    path.to_str().unwrap()
+                ));
+            } else {
+                transfer_detection = Some((file_transfer_detection, path));
+            }
+        }
+    }
+
+    if !conflicts.is_empty() {
+        return conflicts.into_iter().fold(
+            Err(eyre!(
+                "One or more definitions conflict between config files:"
+            )),
+            color_eyre::Help::section,
+        );
+    }
+
+    let importer = FinancialImporter::try_from(FinancialImporterConfiguration {
+        accounts,
+        import_file_definitions,
+        rule_sets,
+        formatting: formatting.unwrap_or_default(),
+        transfer_detection: transfer_detection.map(|(transfer_detection, _)| transfer_detection),
+        definition_files,
+    })?;
+
+    if let Some((journal_path, _)) = accounts_journal {
+        let journal = read_journal(&journal_path, &importer.formatting)?;
+        trace!(
+            "Validating accounts against {} declared in '{}'.",
+            journal.accounts.len(),
+            journal_path.to_str().unwrap()
+        );
+        importer
+            .validate_declared_accounts(&journal.accounts)
+            .wrap_err_with(|| {
+                format!(
+                    "Accounts were not declared in the accounts journal '{}'.",
+                    journal_path.to_str().unwrap()
+                )
+            })?;
+    }
+
     Ok(importer)
 }
 
+fn describe_file_error(path: &Path) -> String {
+    format!(
+        "Encountered errors parsing config file '{}'.",
+        path.to_str().unwrap()
+    )
+}
+
 fn default_config_filename() -> Option<PathBuf> {
     let app_dirs = AppDirs::new(Some(APP_NAME), false)?;
     let config_file_path = app_dirs.config_dir.join(DEFAULT_CONFIG_FILE_NAME);
@@ -1,30 +1,194 @@
+use crate::classifier::CategoryClassifier;
+use crate::ledger_entry::{Commodity, LedgerFormat};
+use crate::transaction_matcher::detection::FormatFingerprint;
+use crate::transfers::TransferDetection;
 use color_eyre::{
-    eyre::{eyre, Error},
+    eyre::{eyre, Error, WrapErr},
     Result,
 };
 use log::trace;
 use regex::{Regex, RegexSet};
-use serde::Deserialize;
-use std::collections::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
 use std::convert::TryFrom;
 use std::iter::once;
+use std::path::PathBuf;
+use voca_rs::case;
 
 // Deserialization and Validation technique borrowed from
 // https://github.com/serde-rs/serde/issues/642#issuecomment-683276351
 pub type AccountMap = HashMap<String, String>;
 pub type ImportFileDefinitionMap = HashMap<String, TransactionMatcher>;
+pub type RuleSetMap = HashMap<String, RuleSetConfiguration>;
+
+// An alias ending in this stands for every account below the account it names.
+pub static ALIAS_WILDCARD_SUFFIX: &str = ".*";
+
+// Resolves an alias to its account name, either directly or through a
+// wildcard alias such as `food.*` for `Expenses:Food`, which resolves
+// `food.dining_out` to `Expenses:Food:Dining Out`. Direct aliases win over
+// wildcards, and longer wildcards over shorter ones.
+#[must_use]
+pub fn resolve_account_alias(accounts: &AccountMap, alias: &str) -> Option<String> {
+    if alias.ends_with(ALIAS_WILDCARD_SUFFIX) {
+        return None;
+    }
+    if let Some(account) = accounts.get(alias) {
+        return Some(account.clone());
+    }
+
+    let (_, account, components) = accounts
+        .iter()
+        .filter_map(|(wildcard, account)| {
+            let prefix = wildcard.strip_suffix(ALIAS_WILDCARD_SUFFIX)?;
+            let components = alias.strip_prefix(prefix)?.strip_prefix('.')?;
+            Some((prefix.len(), account, components))
+        })
+        .max_by_key(|(prefix_length, _, _)| *prefix_length)?;
+
+    let components: Vec<String> = components.split('.').map(case::title_case).collect();
+    if components.iter().any(String::is_empty) {
+        return None;
+    }
+
+    Some(format!("{}:{}", account, components.join(":")))
+}
+
+// Finds the alias of an account name, the reverse of `resolve_account_alias`:
+// a direct alias, or one through the longest wildcard alias of an account
+// above it, such as `food.dining_out` through `food.*` for
+// `Expenses:Food:Dining Out`, as long as it resolves back to the account.
+#[must_use]
+pub fn alias_for_account(accounts: &AccountMap, account: &str) -> Option<String> {
+    let direct_alias = accounts
+        .iter()
+        .filter(|(alias, aliased)| {
+            !alias.ends_with(ALIAS_WILDCARD_SUFFIX) && aliased.as_str() == account
+        })
+        .map(|(alias, _)| alias)
+        .min();
+    if let Some(alias) = direct_alias {
+        return Some(alias.clone());
+    }
+
+    let (_, prefix, components) = accounts
+        .iter()
+        .filter_map(|(wildcard, wildcard_account)| {
+            let prefix = wildcard.strip_suffix(ALIAS_WILDCARD_SUFFIX)?;
+            let components = account
+                .strip_prefix(wildcard_account.as_str())?
+                .strip_prefix(':')?;
+            Some((wildcard_account.len(), prefix, components))
+        })
+        .max_by_key(|(account_length, _, _)| *account_length)?;
+
+    let components: Vec<String> = components.split(':').map(case::snake_case).collect();
+    let alias = format!("{}.{}", prefix, components.join("."));
+    (resolve_account_alias(accounts, &alias).as_deref() == Some(account)).then_some(alias)
+}
 
 #[derive(Deserialize)]
 #[serde(try_from = "FinancialImporterConfiguration")]
 pub struct FinancialImporter {
     pub accounts: AccountMap,
     pub import_file_definitions: ImportFileDefinitionMap,
+    pub formatting: LedgerFormat,
+    // Proposes accounts for records handled by the fallback rule, when loaded.
+    pub classifier: Option<CategoryClassifier>,
+    // Pairs the two sides of transfers between statements processed together, when set.
+    pub transfer_detection: Option<TransferDetection>,
 }
 
 #[derive(Deserialize)]
 pub struct FinancialImporterConfiguration {
     pub accounts: AccountMap,
-    pub import_file_definitions: ImportFileDefinitionMap,
+    pub import_file_definitions: HashMap<String, TransactionMatcherConfiguration>,
+    #[serde(default)]
+    pub rule_sets: RuleSetMap,
+    #[serde(default)]
+    pub formatting: LedgerFormat,
+    pub transfer_detection: Option<TransferDetection>,
+    // The config file each import file definition came from, for error messages.
+    #[serde(skip)]
+    pub definition_files: HashMap<String, PathBuf>,
+}
+
+impl FinancialImporter {
+    // Checks that every account the aliases and rules refer to is one of the
+    // declared accounts, such as those from the `account` directives of a journal.
+    pub fn validate_declared_accounts(&self, declared_accounts: &BTreeSet<String>) -> Result<()> {
+        let explicit_accounts = self
+            .accounts
+            .iter()
+            .filter(|(alias, _)| !alias.ends_with(ALIAS_WILDCARD_SUFFIX))
+            .map(|(_, account)| account.clone());
+        let rule_accounts = self
+            .import_file_definitions
+            .values()
+            .flat_map(|matcher| {
+                matcher
+                    .transaction_rules
+                    .iter()
+                    .chain(once(&matcher.fallback_rule))
+            })
+            .flat_map(|rule| [&rule.account1, &rule.account2])
+            .filter_map(|alias| resolve_account_alias(&self.accounts, alias));
+
+        let undeclared_accounts: BTreeSet<String> = explicit_accounts
+            .chain(rule_accounts)
+            .filter(|account| !declared_accounts.contains(account))
+            .collect();
+
+        if undeclared_accounts.is_empty() {
+            return Ok(());
+        }
+
+        undeclared_accounts
+            .into_iter()
+            .map(
+                |account| match closest_account(&account, declared_accounts) {
+                    Some(closest) => eyre!(
+                        "Account '{}' is not declared. Did you mean '{}'?",
+                        account,
+                        closest
+                    ),
+                    None => eyre!("Account '{}' is not declared.", account),
+                },
+            )
+            .fold(
+                Err(eyre!("One or more accounts are not declared:")),
+                color_eyre::Help::section,
+            )
+    }
+}
+
+// Suggestions are only made for names within this many edits of the account.
+static MAX_SUGGESTION_DISTANCE: usize = 3;
+
+fn closest_account<'a>(account: &str, declared_accounts: &'a BTreeSet<String>) -> Option<&'a str> {
+    declared_accounts
+        .iter()
+        .map(|declared| (edit_distance(account, declared), declared))
+        .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, declared)| declared.as_str())
+}
+
+// The Levenshtein distance between the strings, in characters.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous_row[j] + usize::from(a_char != *b_char);
+            row.push(substitution.min(previous_row[j + 1] + 1).min(row[j] + 1));
+        }
+        previous_row = row;
+    }
+
+    previous_row[b.len()]
 }
 
 impl TryFrom<FinancialImporterConfiguration> for FinancialImporter {
@@ -34,13 +198,43 @@ impl TryFrom<FinancialImporterConfiguration> for FinancialImporter {
         FinancialImporterConfiguration {
             accounts,
             import_file_definitions,
+            rule_sets,
+            formatting,
+            transfer_detection,
+            definition_files,
         }: FinancialImporterConfiguration,
     ) -> Result<Self, Self::Error> {
         trace!("Loaded {} account alias definitions.", accounts.len());
 
+        let describe_definition = |format_name: &str| match definition_files.get(format_name) {
+            Some(path) => format!(
+                "file format definition '{}' of config file '{}'",
+                format_name,
+                path.to_str().unwrap()
+            ),
+            None => format!("file format definition '{}'", format_name),
+        };
+
+        let import_file_definitions = import_file_definitions
+            .into_iter()
+            .map(|(format_name, configuration)| {
+                let matcher = TransactionMatcher::from_configuration(configuration, &rule_sets)
+                    .wrap_err_with(|| format!("Error in {}.", describe_definition(&format_name)))?;
+                Ok((format_name, matcher))
+            })
+            .collect::<Result<ImportFileDefinitionMap>>()?;
+
         let validation_errors: Vec<_> = import_file_definitions
-            .values()
-            .flat_map(|matcher| matcher.validate_rule_account_aliases(&accounts))
+            .iter()
+            .flat_map(|(format_name, matcher)| {
+                let location = describe_definition(format_name);
+                matcher
+                    .validate_rule_account_aliases(&accounts)
+                    .into_iter()
+                    .map(move |validation| {
+                        validation.map_err(|error| eyre!("{} In {}.", error, location))
+                    })
+            })
             .filter(Result::is_err)
             .collect();
 
@@ -48,6 +242,9 @@ impl TryFrom<FinancialImporterConfiguration> for FinancialImporter {
             Ok(FinancialImporter {
                 accounts,
                 import_file_definitions,
+                formatting,
+                classifier: None,
+                transfer_detection,
             })
         } else {
             validation_errors.into_iter().map(Result::unwrap_err).fold(
@@ -67,12 +264,83 @@ pub struct TransactionMatcher {
     pub transaction_rules: Vec<TransactionRule>,
     pub fallback_rule: TransactionRule,
     pub rule_patterns: RegexSet,
+    // The currency that the amounts of the source file are in.
+    pub commodity: Commodity,
+    // The ISO 4217 code of that currency, such as `USD` for `$`, if the symbol is not one.
+    pub currency_code: Option<String>,
+    // The posting that carries a record's original foreign currency amount, if any.
+    pub foreign_amount_posting: Option<RulePosting>,
+    // Identifies the input files of the format, to detect it automatically.
+    pub fingerprint: Option<FormatFingerprint>,
+}
+
+// Identifies one of the two postings generated by a transaction rule.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RulePosting {
+    Account1,
+    Account2,
 }
 
 impl TransactionMatcher {
+    // Builds the matcher with its own rules first, followed by the rules of each
+    // rule set it uses in the order listed, all compiled into one `RegexSet`.
+    pub fn from_configuration(
+        TransactionMatcherConfiguration {
+            currency,
+            currency_code,
+            foreign_amount_posting,
+            fingerprint,
+            fallback_rule,
+            rule_sets: rule_set_names,
+            transaction_rules,
+        }: TransactionMatcherConfiguration,
+        rule_sets: &RuleSetMap,
+    ) -> Result<Self> {
+        let fallback_rule: TransactionRule = TransactionRule::try_from(fallback_rule)?;
+
+        let mut rules: Vec<TransactionRule> = transaction_rules
+            .into_iter()
+            .map(|rule| TransactionRule::for_statement_account(rule, &fallback_rule.account1))
+            .collect::<Result<_>>()?;
+
+        for rule_set_name in &rule_set_names {
+            let rule_set = rule_sets
+                .get(rule_set_name)
+                .ok_or_else(|| eyre!("Rule set '{}' is not defined.", rule_set_name))?;
+            for rule in &rule_set.transaction_rules {
+                let rule = TransactionRule::for_statement_account(
+                    rule.clone(),
+                    &fallback_rule.account1,
+                )
+                .wrap_err_with(|| format!("Error in {}.", rule_set.describe(rule_set_name)))?;
+                rules.push(rule);
+            }
+        }
+
+        let patterns = rules.iter().map(|rule| &rule.pattern_string);
+        let rule_patterns: RegexSet = RegexSet::new(patterns)?;
+        trace!(
+            "Loaded matcher with {} compiled patterns from {} rule sets.",
+            rule_patterns.len(),
+            rule_set_names.len()
+        );
+
+        Ok(TransactionMatcher {
+            transaction_rules: rules,
+            fallback_rule,
+            rule_patterns,
+            commodity: currency.unwrap_or_default(),
+            currency_code,
+            foreign_amount_posting,
+            fingerprint,
+        })
+    }
+
     fn validate_rule_account_aliases(&self, accounts: &AccountMap) -> Vec<Result<()>> {
         self.transaction_rules
             .iter()
+            .chain(once(&self.fallback_rule))
             .flat_map(|rule| {
                 // Ugly, but: https://users.rust-lang.org/t/flattening-a-vector-of-tuples/11409/4
                 let validations = rule.validate_account_aliases(accounts);
@@ -84,38 +352,50 @@ impl TransactionMatcher {
 
 #[derive(Deserialize)]
 pub struct TransactionMatcherConfiguration {
+    pub currency: Option<Commodity>,
+    pub currency_code: Option<String>,
+    pub foreign_amount_posting: Option<RulePosting>,
+    pub fingerprint: Option<FormatFingerprint>,
     pub fallback_rule: FallbackRuleConfiguration,
-    pub transaction_rules: Vec<TransactionRule>,
+    // Names of the rule sets whose rules are tried after this format's own.
+    #[serde(default)]
+    pub rule_sets: Vec<String>,
+    #[serde(default)]
+    pub transaction_rules: Vec<TransactionRuleConfiguration>,
 }
 
 impl TryFrom<TransactionMatcherConfiguration> for TransactionMatcher {
     type Error = Error;
 
-    fn try_from(
-        TransactionMatcherConfiguration {
-            fallback_rule,
-            transaction_rules,
-        }: TransactionMatcherConfiguration,
-    ) -> Result<Self, Self::Error> {
-        let patterns = transaction_rules.iter().map(|rule| &rule.pattern_string);
-        let rule_patterns: RegexSet = RegexSet::new(patterns)?;
-        trace!(
-            "Loaded matcher with {} compiled patterns.",
-            rule_patterns.len()
-        );
+    fn try_from(configuration: TransactionMatcherConfiguration) -> Result<Self, Self::Error> {
+        TransactionMatcher::from_configuration(configuration, &RuleSetMap::new())
+    }
+}
 
-        let fallback_rule: TransactionRule = TransactionRule::try_from(fallback_rule)?;
+// Transaction rules shared by the import file definitions that list the set
+// in their `rule_sets`.
+#[derive(Deserialize)]
+pub struct RuleSetConfiguration {
+    pub transaction_rules: Vec<TransactionRuleConfiguration>,
+    // The config file the rule set came from, for error messages.
+    #[serde(skip)]
+    pub source_file: Option<PathBuf>,
+}
 
-        let matcher = TransactionMatcher {
-            transaction_rules,
-            fallback_rule,
-            rule_patterns,
-        };
-        Ok(matcher)
+impl RuleSetConfiguration {
+    fn describe(&self, name: &str) -> String {
+        match &self.source_file {
+            Some(path) => format!(
+                "rule set '{}' of config file '{}'",
+                name,
+                path.to_str().unwrap()
+            ),
+            None => format!("rule set '{}'", name),
+        }
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 #[serde(try_from = "TransactionRuleConfiguration")]
 pub struct TransactionRule {
     pub name: String,
@@ -130,6 +410,18 @@ pub struct TransactionRule {
 }
 
 impl TransactionRule {
+    // Rules without an `account1` are for the statement account of the format
+    // using them, which is that of its fallback rule.
+    fn for_statement_account(
+        mut configuration: TransactionRuleConfiguration,
+        statement_account: &str,
+    ) -> Result<Self> {
+        configuration
+            .account1
+            .get_or_insert_with(|| String::from(statement_account));
+        TransactionRule::try_from(configuration)
+    }
+
     fn validate_account_aliases(&self, accounts: &AccountMap) -> (Result<()>, Result<()>) {
         (
             self.validate_alias(&self.account1, accounts),
@@ -138,25 +430,35 @@ impl TransactionRule {
     }
 
     fn validate_alias(&self, account_alias: &str, accounts: &AccountMap) -> Result<()> {
-        if accounts.contains_key(account_alias) {
-            Ok(())
-        } else {
-            Err(eyre!(format!(
+        self.resolve_alias(account_alias, accounts).map(|_| ())
+    }
+
+    pub(crate) fn resolve_alias(
+        &self,
+        account_alias: &str,
+        accounts: &AccountMap,
+    ) -> Result<String> {
+        resolve_account_alias(accounts, account_alias).ok_or_else(|| {
+            eyre!(format!(
                 "Account Alias '{}' from Transaction Rule '{}' is not defined.",
                 account_alias, self.name
-            )))
-        }
+            ))
+        })
     }
 }
 
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize, Serialize)]
 pub struct TransactionRuleConfiguration {
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub name: Option<String>,
     pub pattern_string: String,
-    pub account1: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account1: Option<String>,
     pub account2: String,
     pub payee: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub needs_finalized: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub negate_first_amount: Option<bool>,
 }
 
@@ -180,6 +482,8 @@ impl TryFrom<TransactionRuleConfiguration> for TransactionRule {
                 format!("Payee: '{}' with pattern '{}'.", payee, pattern_string)
             }
         };
+        let account1 =
+            account1.ok_or_else(|| eyre!("Transaction Rule '{}' has no account1.", name_string))?;
         let needs_finalized_bool: bool = needs_finalized.unwrap_or(false);
         let negate_first_amount_bool: bool = negate_first_amount.unwrap_or(false);
 
@@ -187,7 +491,13 @@ impl TryFrom<TransactionRuleConfiguration> for TransactionRule {
         let payee_is_template: bool = payee.contains('$');
 
         // First compile the regex here to make sure it's valid
-        let pattern_re: Regex = Regex::new(pattern_string.as_str())?;
+        let pattern_re: Regex = Regex::new(pattern_string.as_str()).map_err(|error| {
+            eyre!(
+                "Invalid pattern in Transaction Rule '{}': {}",
+                name_string,
+                error
+            )
+        })?;
         // We only need to keep a separate Regex for the rule if the Payee is a template
         // and thus requiring captures, which are not available for RegexSet.
         let pattern: Option<Regex> = if payee_is_template {
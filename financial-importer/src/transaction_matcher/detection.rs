@@ -0,0 +1,207 @@
+use crate::ofx;
+use crate::transaction_matcher::definitions::FinancialImporter;
+use color_eyre::eyre::{eyre, Error, Result, WrapErr};
+use glob::Pattern;
+use lazy_static::lazy_static;
+use log::trace;
+use regex::Regex;
+use serde::Deserialize;
+use std::convert::TryFrom;
+use std::path::Path;
+
+lazy_static! {
+    static ref OFX_BANK_ID: Regex = Regex::new(r"(?i)<BANKID>\s*([^<\s]+)").unwrap();
+}
+
+// What identifies the input files of an import file definition. Every
+// criterion given must match for a file to be detected as the format.
+#[derive(Debug, Deserialize)]
+#[serde(try_from = "FormatFingerprintConfiguration")]
+pub struct FormatFingerprint {
+    pub header: Option<Vec<String>>,
+    pub column_count: Option<usize>,
+    pub filename_pattern: Option<Pattern>,
+    pub ofx_bank_id: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct FormatFingerprintConfiguration {
+    pub header: Option<Vec<String>>,
+    pub column_count: Option<usize>,
+    pub filename_glob: Option<String>,
+    pub ofx_bank_id: Option<String>,
+}
+
+impl TryFrom<FormatFingerprintConfiguration> for FormatFingerprint {
+    type Error = Error;
+
+    fn try_from(
+        FormatFingerprintConfiguration {
+            header,
+            column_count,
+            filename_glob,
+            ofx_bank_id,
+        }: FormatFingerprintConfiguration,
+    ) -> Result<Self, Self::Error> {
+        if header.is_none()
+            && column_count.is_none()
+            && filename_glob.is_none()
+            && ofx_bank_id.is_none()
+        {
+            return Err(eyre!(
+                "A fingerprint needs at least one of header, column_count, filename_glob or ofx_bank_id."
+            ));
+        }
+
+        let filename_pattern = filename_glob
+            .map(|glob| {
+                Pattern::new(&glob)
+                    .map_err(|error| eyre!("Invalid filename_glob '{}': {}", glob, error))
+            })
+            .transpose()?;
+
+        Ok(FormatFingerprint {
+            header,
+            column_count,
+            filename_pattern,
+            ofx_bank_id,
+        })
+    }
+}
+
+// The parts of an input file that fingerprints are checked against.
+pub struct InputFileSignature {
+    pub file_name: String,
+    // The header row of a CSV file.
+    pub header: Option<Vec<String>>,
+    // The bank ID of an OFX file.
+    pub ofx_bank_id: Option<String>,
+}
+
+impl InputFileSignature {
+    pub fn for_file(input_file: &Path) -> Result<Self> {
+        let contents = std::fs::read(input_file).wrap_err_with(|| {
+            format!(
+                "Encountered errors reading input file '{}'.",
+                input_file.to_str().unwrap()
+            )
+        })?;
+        let text = String::from_utf8_lossy(&contents);
+        let file_name = input_file
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        if ofx::is_ofx(&text) {
+            let ofx_bank_id = OFX_BANK_ID
+                .captures(&text)
+                .map(|captures| String::from(&captures[1]));
+            return Ok(InputFileSignature {
+                file_name,
+                header: None,
+                ofx_bank_id,
+            });
+        }
+
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .from_reader(contents.as_slice());
+        let header = reader.records().next().transpose()?.map(|record| {
+            record
+                .iter()
+                .map(|field| String::from(field.trim()))
+                .collect()
+        });
+
+        Ok(InputFileSignature {
+            file_name,
+            header,
+            ofx_bank_id: None,
+        })
+    }
+}
+
+impl FormatFingerprint {
+    #[must_use]
+    pub fn matches(&self, signature: &InputFileSignature) -> bool {
+        let header_matches = self.header.as_ref().is_none_or(|expected| {
+            signature.header.as_ref().is_some_and(|header| {
+                header.len() == expected.len()
+                    && header
+                        .iter()
+                        .zip(expected)
+                        .all(|(column, expected)| column.eq_ignore_ascii_case(expected.trim()))
+            })
+        });
+        let column_count_matches = self.column_count.is_none_or(|expected| {
+            signature
+                .header
+                .as_ref()
+                .is_some_and(|header| header.len() == expected)
+        });
+        let filename_matches = self
+            .filename_pattern
+            .as_ref()
+            .is_none_or(|pattern| pattern.matches(&signature.file_name));
+        let bank_id_matches = self
+            .ofx_bank_id
+            .as_ref()
+            .is_none_or(|expected| signature.ofx_bank_id.as_deref() == Some(expected.as_str()));
+
+        header_matches && column_count_matches && filename_matches && bank_id_matches
+    }
+}
+
+impl FinancialImporter {
+    // The names of the formats whose fingerprints match the file.
+    pub fn matching_formats(&self, input_file: &Path) -> Result<Vec<&str>> {
+        let signature = InputFileSignature::for_file(input_file)?;
+
+        let mut format_names: Vec<&str> = self
+            .import_file_definitions
+            .iter()
+            .filter(|(_, matcher)| {
+                matcher
+                    .fingerprint
+                    .as_ref()
+                    .is_some_and(|fingerprint| fingerprint.matches(&signature))
+            })
+            .map(|(format_name, _)| format_name.as_str())
+            .collect();
+        format_names.sort_unstable();
+
+        Ok(format_names)
+    }
+
+    // The given format, or else the one format whose fingerprint matches the file.
+    pub fn format_for_file(
+        &self,
+        format_name: Option<String>,
+        input_file: &Path,
+    ) -> Result<String> {
+        if let Some(format_name) = format_name {
+            return Ok(format_name);
+        }
+
+        match self.matching_formats(input_file)?.as_slice() {
+            [format_name] => {
+                trace!(
+                    "Detected file format definition '{}' for '{}'.",
+                    format_name,
+                    input_file.to_str().unwrap()
+                );
+                Ok(String::from(*format_name))
+            }
+            [] => Err(eyre!(
+                "No file format definition's fingerprint matches '{}', use -f to choose one.",
+                input_file.to_str().unwrap()
+            )),
+            format_names => Err(eyre!(
+                "The fingerprints of several file format definitions match '{}': {}. Use -f to choose one.",
+                input_file.to_str().unwrap(),
+                format_names.join(", ")
+            )),
+        }
+    }
+}
@@ -0,0 +1,181 @@
+use crate::transaction_matcher::config::{
+    configuration_from_files, read_configuration_files, ConfigurationFile,
+};
+use crate::transaction_matcher::definitions::{FinancialImporter, TransactionRuleConfiguration};
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use log::trace;
+use std::path::{Path, PathBuf};
+use toml_edit::{value, ArrayOfTables, DocumentMut, Item, Table};
+
+static ACCOUNTS_KEY: &str = "accounts";
+static IMPORT_FILE_DEFINITIONS_KEY: &str = "import_file_definitions";
+static TRANSACTION_RULES_KEY: &str = "transaction_rules";
+
+struct EditedFile {
+    path: PathBuf,
+    document: DocumentMut,
+    changed: bool,
+}
+
+// Makes changes to a configuration file and the files it includes while
+// keeping their comments, formatting and ordering. Changes are validated like
+// a freshly loaded configuration before saving. The configuration model is
+// only deserialized, as writing it back would drop the comments and merge the
+// included files, so the edits are made to the TOML documents themselves.
+pub struct ConfigurationEditor {
+    // The main configuration file comes first.
+    files: Vec<EditedFile>,
+}
+
+impl ConfigurationEditor {
+    pub fn load(config_file: &Path) -> Result<Self> {
+        let files = read_configuration_files(config_file)?
+            .into_iter()
+            .map(|ConfigurationFile { path, contents }| {
+                let document: DocumentMut = contents.parse().wrap_err_with(|| {
+                    format!(
+                        "Encountered errors parsing config file '{}'.",
+                        path.to_str().unwrap()
+                    )
+                })?;
+                Ok(EditedFile {
+                    path,
+                    document,
+                    changed: false,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(ConfigurationEditor { files })
+    }
+
+    // Adds the alias to the first file with an `[accounts]` table, or the main file.
+    pub fn add_account(&mut self, alias: &str, account: &str) -> Result<()> {
+        if let Some(existing) = self
+            .files
+            .iter()
+            .find_map(|file| file.document.get(ACCOUNTS_KEY)?.get(alias))
+        {
+            return Err(eyre!(
+                "Account alias '{}' is already defined as '{}'.",
+                alias,
+                existing.as_str().unwrap_or_default()
+            ));
+        }
+
+        let index = self
+            .files
+            .iter()
+            .position(|file| file.document.contains_key(ACCOUNTS_KEY))
+            .unwrap_or(0);
+        let file = &mut self.files[index];
+        let accounts = file
+            .document
+            .entry(ACCOUNTS_KEY)
+            .or_insert_with(|| Item::Table(Table::new()))
+            .as_table_like_mut()
+            .ok_or_else(|| {
+                eyre!(
+                    "'{}' in config file '{}' is not a table.",
+                    ACCOUNTS_KEY,
+                    file.path.to_str().unwrap()
+                )
+            })?;
+
+        accounts.insert(alias, value(account));
+        file.changed = true;
+        trace!("Added account alias '{}' for '{}'.", alias, account);
+
+        Ok(())
+    }
+
+    // Adds the rule after the format's own existing rules, in the file that
+    // defines the format, so it is tried before those of its rule sets.
+    pub fn add_transaction_rule(
+        &mut self,
+        format_name: &str,
+        rule: &TransactionRuleConfiguration,
+    ) -> Result<()> {
+        let file = self
+            .files
+            .iter_mut()
+            .find(|file| {
+                file.document
+                    .get(IMPORT_FILE_DEFINITIONS_KEY)
+                    .and_then(|definitions| definitions.get(format_name))
+                    .is_some()
+            })
+            .ok_or_else(|| eyre!("File format definition '{}' not found.", format_name))?;
+        let definition = file.document[IMPORT_FILE_DEFINITIONS_KEY][format_name]
+            .as_table_like_mut()
+            .ok_or_else(|| {
+                eyre!(
+                    "File format definition '{}' in config file '{}' is not a table.",
+                    format_name,
+                    file.path.to_str().unwrap()
+                )
+            })?;
+
+        let rule_table: Table = toml_edit::ser::to_document(rule)?.as_table().clone();
+
+        match definition.get_mut(TRANSACTION_RULES_KEY) {
+            Some(Item::ArrayOfTables(rules)) => rules.push(rule_table),
+            Some(Item::Value(toml_edit::Value::Array(rules))) => {
+                rules.push(rule_table.into_inline_table());
+            }
+            Some(_) => {
+                return Err(eyre!(
+                    "'{}' of file format definition '{}' is not a list of rules.",
+                    TRANSACTION_RULES_KEY,
+                    format_name
+                ))
+            }
+            None => {
+                let mut rules = ArrayOfTables::new();
+                rules.push(rule_table);
+                definition.insert(TRANSACTION_RULES_KEY, Item::ArrayOfTables(rules));
+            }
+        }
+
+        file.changed = true;
+        trace!(
+            "Added transaction rule with pattern '{}' to '{}'.",
+            rule.pattern_string,
+            format_name
+        );
+
+        Ok(())
+    }
+
+    // Runs the edited configuration through the same validation as loading it.
+    pub fn validate(&self) -> Result<FinancialImporter> {
+        let files: Vec<ConfigurationFile> = self
+            .files
+            .iter()
+            .map(|file| ConfigurationFile {
+                path: file.path.clone(),
+                contents: file.document.to_string(),
+            })
+            .collect();
+
+        configuration_from_files(&files).wrap_err("The changed configuration is not valid.")
+    }
+
+    // Writes the changed files, returning their paths.
+    pub fn save(&self) -> Result<Vec<&Path>> {
+        self.validate()?;
+
+        let mut saved_paths = Vec::new();
+        for file in self.files.iter().filter(|file| file.changed) {
+            std::fs::write(&file.path, file.document.to_string()).wrap_err_with(|| {
+                format!(
+                    "Encountered errors writing config file '{}'.",
+                    file.path.to_str().unwrap()
+                )
+            })?;
+            saved_paths.push(file.path.as_path());
+        }
+
+        Ok(saved_paths)
+    }
+}
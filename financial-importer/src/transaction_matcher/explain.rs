@@ -0,0 +1,109 @@
+use crate::classifier::CategoryClassifier;
+use crate::ledger_entry::LedgerEntry;
+use crate::source_record::SourceRecord;
+use crate::transaction_matcher::definitions::{
+    resolve_account_alias, AccountMap, TransactionMatcher, TransactionRule,
+};
+use color_eyre::eyre::Result;
+use regex::Regex;
+
+// How a transaction rule matched a description.
+pub struct RuleMatch<'a> {
+    // The position of the rule among those of the format, from zero.
+    pub rule_index: usize,
+    pub rule: &'a TransactionRule,
+    // The groups the pattern captured, by name or number, with their values.
+    pub captures: Vec<(String, String)>,
+    pub payee: String,
+    pub account1: String,
+    pub account2: String,
+}
+
+// Every rule that matched a description, in the order they are tried, so
+// that the first is the one used, along with the entry it produces.
+pub struct MatchExplanation<'a> {
+    pub rule_matches: Vec<RuleMatch<'a>>,
+    pub rule_count: usize,
+    // The fallback rule, with its accounts, when no rule matched.
+    pub fallback_match: Option<RuleMatch<'a>>,
+    pub ledger_entry: LedgerEntry,
+}
+
+impl TransactionMatcher {
+    pub fn explain<'a>(
+        &'a self,
+        accounts: &AccountMap,
+        classifier: Option<&CategoryClassifier>,
+        record: &SourceRecord,
+    ) -> Result<MatchExplanation<'a>> {
+        let rule_matches: Vec<RuleMatch> = self
+            .rule_patterns
+            .matches(&record.description)
+            .into_iter()
+            .map(|rule_index| {
+                rule_match(
+                    rule_index,
+                    &self.transaction_rules[rule_index],
+                    accounts,
+                    &record.description,
+                )
+            })
+            .collect::<Result<_>>()?;
+
+        let fallback_match = if rule_matches.is_empty() {
+            let mut fallback_match = rule_match(
+                self.transaction_rules.len(),
+                &self.fallback_rule,
+                accounts,
+                &record.description,
+            )?;
+            // The fallback pattern matches everything and captures nothing of use
+            fallback_match.captures.clear();
+            Some(fallback_match)
+        } else {
+            None
+        };
+
+        let ledger_entry = self
+            .ledger_entry_for_source_record(accounts, classifier, record)?
+            .unwrap_entry();
+
+        Ok(MatchExplanation {
+            rule_matches,
+            rule_count: self.transaction_rules.len(),
+            fallback_match,
+            ledger_entry,
+        })
+    }
+}
+
+fn rule_match<'a>(
+    rule_index: usize,
+    rule: &'a TransactionRule,
+    accounts: &AccountMap,
+    description: &str,
+) -> Result<RuleMatch<'a>> {
+    // Rules only keep their compiled pattern for templated payees
+    let pattern = Regex::new(&rule.pattern_string)?;
+    let captures = match pattern.captures(description) {
+        Some(captures) => pattern
+            .capture_names()
+            .enumerate()
+            .filter_map(|(group, name)| {
+                let value = captures.get(group)?.as_str();
+                let group_name = name.map_or_else(|| group.to_string(), String::from);
+                Some((group_name, String::from(value)))
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+
+    Ok(RuleMatch {
+        rule_index,
+        rule,
+        captures,
+        payee: rule.payee_for_description(description),
+        account1: resolve_account_alias(accounts, &rule.account1).unwrap_or_default(),
+        account2: resolve_account_alias(accounts, &rule.account2).unwrap_or_default(),
+    })
+}
@@ -3,20 +3,28 @@ use log::trace;
 use source_record::SourceRecord;
 use voca_rs::case;
 
-use crate::ledger_entry::{LedgerEntry, LedgerEntryBuilder};
+use crate::classifier::CategoryClassifier;
+use crate::ledger_entry::{
+    Amount, Commodity, EntryLine, LedgerEntry, LedgerEntryBuilder, PostingPrice,
+};
 use crate::source_record;
 
-use super::definitions::{AccountMap, FinancialImporter, TransactionMatcher, TransactionRule};
+use super::definitions::{
+    resolve_account_alias, AccountMap, FinancialImporter, RulePosting, TransactionMatcher,
+    TransactionRule,
+};
 
 #[derive(Debug)]
 pub enum GeneratedLedgerEntry<'a> {
     ByMatchedRule {
         ledger_entry: LedgerEntry,
         source_record: &'a SourceRecord,
+        rule_name: String,
     },
     ByFallback {
         ledger_entry: LedgerEntry,
         source_record: &'a SourceRecord,
+        rule_name: String,
     },
 }
 
@@ -24,28 +32,16 @@ impl<'a> GeneratedLedgerEntry<'a> {
     #[must_use]
     pub fn unwrap_entry(self) -> LedgerEntry {
         match self {
-            GeneratedLedgerEntry::ByMatchedRule {
-                ledger_entry,
-                source_record: _,
-            }
-            | GeneratedLedgerEntry::ByFallback {
-                ledger_entry,
-                source_record: _,
-            } => ledger_entry,
+            GeneratedLedgerEntry::ByMatchedRule { ledger_entry, .. }
+            | GeneratedLedgerEntry::ByFallback { ledger_entry, .. } => ledger_entry,
         }
     }
 
     #[must_use]
     pub fn unwrap_source_record(self) -> &'a SourceRecord {
         match self {
-            GeneratedLedgerEntry::ByMatchedRule {
-                ledger_entry: _,
-                source_record,
-            }
-            | GeneratedLedgerEntry::ByFallback {
-                ledger_entry: _,
-                source_record,
-            } => source_record,
+            GeneratedLedgerEntry::ByMatchedRule { source_record, .. }
+            | GeneratedLedgerEntry::ByFallback { source_record, .. } => source_record,
         }
     }
 
@@ -55,14 +51,25 @@ impl<'a> GeneratedLedgerEntry<'a> {
             GeneratedLedgerEntry::ByMatchedRule {
                 ledger_entry,
                 source_record,
+                ..
             }
             | GeneratedLedgerEntry::ByFallback {
                 ledger_entry,
                 source_record,
+                ..
             } => (ledger_entry, source_record),
         }
     }
 
+    // The name of the transaction rule, or fallback rule, the entry was generated by.
+    #[must_use]
+    pub fn rule_name(&self) -> &str {
+        match self {
+            GeneratedLedgerEntry::ByMatchedRule { rule_name, .. }
+            | GeneratedLedgerEntry::ByFallback { rule_name, .. } => rule_name,
+        }
+    }
+
     #[must_use]
     pub fn is_from_matched_rule(&self) -> bool {
         matches!(*self, GeneratedLedgerEntry::ByMatchedRule { .. })
@@ -70,22 +77,25 @@ impl<'a> GeneratedLedgerEntry<'a> {
 }
 
 impl FinancialImporter {
-    pub fn ledger_entry_for_source_record<'a>(
-        &self,
-        file_format: &str,
-        record: &'a SourceRecord,
-    ) -> Result<GeneratedLedgerEntry<'a>> {
-        let matcher: &TransactionMatcher = self
-            .import_file_definitions
+    pub fn matcher_for_format(&self, file_format: &str) -> Result<&TransactionMatcher> {
+        self.import_file_definitions
             .get(file_format)
             .ok_or_else(|| {
                 eyre!(format!(
                     "File format definition '{}' not found.",
                     file_format
                 ))
-            })?;
+            })
+    }
+
+    pub fn ledger_entry_for_source_record<'a>(
+        &self,
+        file_format: &str,
+        record: &'a SourceRecord,
+    ) -> Result<GeneratedLedgerEntry<'a>> {
+        let matcher: &TransactionMatcher = self.matcher_for_format(file_format)?;
 
-        matcher.ledger_entry_for_source_record(&self.accounts, record)
+        matcher.ledger_entry_for_source_record(&self.accounts, self.classifier.as_ref(), record)
     }
 }
 
@@ -93,6 +103,7 @@ impl TransactionMatcher {
     pub fn ledger_entry_for_source_record<'a>(
         &self,
         accounts: &AccountMap,
+        classifier: Option<&CategoryClassifier>,
         record: &'a SourceRecord,
     ) -> Result<GeneratedLedgerEntry<'a>> {
         trace!("Attempting to match for record '{}'.", record.description);
@@ -109,13 +120,11 @@ impl TransactionMatcher {
                 record.description
             );
 
-            match self
-                .fallback_rule
-                .ledger_entry_for_source_record(accounts, record)
-            {
+            match self.fallback_ledger_entry(accounts, classifier, record) {
                 Ok(ledger_entry) => Ok(GeneratedLedgerEntry::ByFallback {
                     ledger_entry,
                     source_record: record,
+                    rule_name: self.fallback_rule.name.clone(),
                 }),
                 Err(e) => Err(e),
             }
@@ -141,10 +150,17 @@ impl TransactionMatcher {
                 rule.pattern_string
             );
 
-            match rule.ledger_entry_for_source_record(accounts, record) {
+            match rule.ledger_entry_for_source_record(
+                accounts,
+                &self.commodity,
+                self.currency_code.as_deref(),
+                self.foreign_amount_posting,
+                record,
+            ) {
                 Ok(ledger_entry) => Ok(GeneratedLedgerEntry::ByMatchedRule {
                     ledger_entry,
                     source_record: record,
+                    rule_name: rule.name.clone(),
                 }),
                 Err(e) => Err(e),
             }
@@ -152,20 +168,85 @@ impl TransactionMatcher {
     }
 }
 
-static MATCHING_RULE_COMMENT: &str = "MATCHING RULE";
-static NEEDS_FINALIZED_COMMENT: &str = "NEEDS FINALIZED";
-static SOURCE_COMMENT: &str = "SOURCE";
-
-impl TransactionRule {
-    pub fn ledger_entry_for_source_record(
+impl TransactionMatcher {
+    // Uses the fallback rule, with its second account replaced by the one the
+    // classifier predicts, if any.
+    fn fallback_ledger_entry(
         &self,
-        account_map: &AccountMap,
+        accounts: &AccountMap,
+        classifier: Option<&CategoryClassifier>,
         record: &SourceRecord,
     ) -> Result<LedgerEntry> {
-        let payee = if self.payee_is_template {
+        let prediction = classifier
+            .and_then(|classifier| classifier.predict(&record.description, record.amount))
+            .filter(|prediction| {
+                resolve_account_alias(accounts, &prediction.account_alias).is_some()
+            });
+
+        let Some(prediction) = prediction else {
+            return self.fallback_rule.ledger_entry_for_source_record(
+                accounts,
+                &self.commodity,
+                self.currency_code.as_deref(),
+                self.foreign_amount_posting,
+                record,
+            );
+        };
+
+        trace!(
+            "Classifier predicted account '{}' with score {:.2} for record '{}'.",
+            prediction.account_alias,
+            prediction.score,
+            record.description
+        );
+
+        let rule = TransactionRule {
+            account2: prediction.account_alias.clone(),
+            ..self.fallback_rule.clone()
+        };
+        let mut ledger_entry = rule.ledger_entry_for_source_record(
+            accounts,
+            &self.commodity,
+            self.currency_code.as_deref(),
+            self.foreign_amount_posting,
+            record,
+        )?;
+        ledger_entry.add_comment(format!(
+            "{}: {} (score {:.2})",
+            PREDICTED_ACCOUNT_COMMENT,
+            resolve_account_alias(accounts, &prediction.account_alias).unwrap(),
+            prediction.score
+        ));
+
+        Ok(ledger_entry)
+    }
+}
+
+pub static MATCHING_RULE_COMMENT: &str = "MATCHING RULE";
+pub static NEEDS_FINALIZED_COMMENT: &str = "NEEDS FINALIZED";
+static PREDICTED_ACCOUNT_COMMENT: &str = "PREDICTED ACCOUNT";
+pub static SOURCE_COMMENT: &str = "SOURCE";
+
+// The description of the source record the entry was generated from.
+#[must_use]
+pub fn source_description(entry: &LedgerEntry) -> Option<&str> {
+    entry.lines.iter().find_map(|line| match line {
+        EntryLine::Comment { comment } => comment
+            .strip_prefix(SOURCE_COMMENT)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .map(str::trim),
+        EntryLine::Posting { .. } => None,
+    })
+}
+
+impl TransactionRule {
+    // The payee, with any template expanded from the captures of the pattern.
+    #[must_use]
+    pub fn payee_for_description(&self, description: &str) -> String {
+        if self.payee_is_template {
             if let Some(pattern) = &self.pattern {
                 let mut payee = String::new();
-                let templates = pattern.captures(&record.description).unwrap();
+                let templates = pattern.captures(description).unwrap();
                 templates.expand(&self.payee, &mut payee);
                 case::title_case(payee.as_str())
             } else {
@@ -173,7 +254,18 @@ impl TransactionRule {
             }
         } else {
             self.payee.clone()
-        };
+        }
+    }
+
+    pub fn ledger_entry_for_source_record(
+        &self,
+        account_map: &AccountMap,
+        commodity: &Commodity,
+        currency_code: Option<&str>,
+        foreign_amount_posting: Option<RulePosting>,
+        record: &SourceRecord,
+    ) -> Result<LedgerEntry> {
+        let payee = self.payee_for_description(&record.description);
 
         let mut entry_builder: LedgerEntryBuilder = LedgerEntryBuilder::new(record.date, payee);
 
@@ -186,17 +278,102 @@ impl TransactionRule {
             entry_builder.add_comment(NEEDS_FINALIZED_COMMENT.to_string());
         }
 
-        let account1 = account_map.get(&self.account1).unwrap();
-        let account2 = account_map.get(&self.account2).unwrap();
+        let account1 = self.resolve_alias(&self.account1, account_map)?;
+        let account2 = self.resolve_alias(&self.account2, account_map)?;
+
+        let amount = Amount::new(record.amount, commodity.clone());
 
-        if self.negate_first_amount {
-            entry_builder.add_posting(account1.clone(), -record.amount);
-            entry_builder.add_posting(account2.clone(), record.amount);
+        let (amount1, amount2) = if self.negate_first_amount {
+            (-amount.clone(), amount)
         } else {
-            entry_builder.add_posting(account1.clone(), record.amount);
-            entry_builder.add_posting(account2.clone(), -record.amount);
+            (amount.clone(), -amount)
+        };
+
+        let foreign_quantity = foreign_quantity_for_record(commodity, currency_code, record);
+        for (posting, account, amount) in [
+            (RulePosting::Account1, account1, amount1),
+            (RulePosting::Account2, account2, amount2),
+        ] {
+            match foreign_quantity {
+                Some((quantity, ref foreign_commodity))
+                    if foreign_amount_posting == Some(posting) =>
+                {
+                    // The foreign amount takes the sign of the posting, priced at
+                    // its total in the currency of the statement.
+                    let foreign_amount = Amount::new(
+                        quantity.abs().copysign(amount.quantity),
+                        foreign_commodity.clone(),
+                    );
+                    let price =
+                        PostingPrice::Total(Amount::new(amount.quantity.abs(), amount.commodity));
+                    entry_builder.add_posting_with_price(account, foreign_amount, price);
+                }
+                _ => entry_builder.add_posting(account, amount),
+            }
         }
 
         entry_builder.build()
     }
 }
+
+// The original amount of a record, when the statement reports one in a different currency.
+// Statements may give their own currency by its ISO code, such as USD on a `$`
+// statement, so the currency is compared with both the symbol and the code.
+fn foreign_quantity_for_record(
+    commodity: &Commodity,
+    currency_code: Option<&str>,
+    record: &SourceRecord,
+) -> Option<(f64, Commodity)> {
+    match (record.original_amount, &record.original_currency) {
+        (Some(quantity), Some(currency))
+            if !currency.is_empty()
+                && currency.as_str() != commodity.symbol.as_str()
+                && !currency_code.is_some_and(|code| currency.eq_ignore_ascii_case(code)) =>
+        {
+            Some((quantity, Commodity::new(currency)))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn record(amount: f64, original_amount: f64, original_currency: &str) -> SourceRecord {
+        SourceRecord {
+            date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            description: String::from("STORE"),
+            amount,
+            original_amount: Some(original_amount),
+            original_currency: Some(String::from(original_currency)),
+            fitid: None,
+            line_number: None,
+        }
+    }
+
+    #[test]
+    fn original_amount_in_the_statement_currency_is_not_foreign() {
+        let commodity = Commodity::new("$");
+
+        assert_eq!(
+            foreign_quantity_for_record(&commodity, Some("USD"), &record(-12.5, 12.5, "USD")),
+            None
+        );
+        assert_eq!(
+            foreign_quantity_for_record(&commodity, Some("USD"), &record(-12.5, 11.4, "EUR")),
+            Some((11.4, Commodity::new("EUR")))
+        );
+    }
+
+    #[test]
+    fn original_amount_equal_to_the_amount_in_another_currency_is_foreign() {
+        let commodity = Commodity::new("$");
+
+        assert_eq!(
+            foreign_quantity_for_record(&commodity, Some("USD"), &record(-25.0, 25.0, "BMD")),
+            Some((25.0, Commodity::new("BMD")))
+        );
+    }
+}
@@ -1,7 +1,12 @@
 pub mod config;
 pub mod definitions;
+pub mod detection;
+pub mod editor;
+pub mod explain;
 pub mod matcher;
 
-pub use config::load_configuration;
+pub use config::{config_file_path, load_configuration};
 pub use definitions::FinancialImporter;
+pub use editor::ConfigurationEditor;
+pub use explain::{MatchExplanation, RuleMatch};
 pub use matcher::GeneratedLedgerEntry;
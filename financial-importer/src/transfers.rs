@@ -0,0 +1,248 @@
+use crate::ledger_entry::{Amount, EntryLine, LedgerEntry, LedgerEntryBuilder};
+use crate::transaction_matcher::matcher::{
+    source_description, MATCHING_RULE_COMMENT, SOURCE_COMMENT,
+};
+use chrono::NaiveDate;
+use color_eyre::eyre::{eyre, Error, Result};
+use log::trace;
+use regex::Regex;
+use serde::Deserialize;
+use std::convert::TryFrom;
+
+static DEFAULT_DATE_WINDOW_DAYS: i64 = 3;
+static TRANSFER_PAYEE: &str = "Transfer";
+static TRANSFER_RULE_NAME: &str = "transfer detection";
+static TRANSFER_AMOUNTS_COMMENT: &str = "TRANSFER AMOUNTS";
+
+// How records of different statements are paired up as the two sides of a
+// transfer between their accounts, such as paying a credit card from checking.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(try_from = "TransferDetectionConfiguration")]
+pub struct TransferDetection {
+    // The most days apart the two sides may be posted.
+    pub date_window_days: i64,
+    // The most the amounts of the two sides may differ by.
+    pub amount_tolerance: f64,
+    // Only records with a matching description can be a side of a transfer, so
+    // that unrelated purchases and refunds of the same amount are not paired.
+    pub pattern: Regex,
+}
+
+#[derive(Deserialize)]
+pub struct TransferDetectionConfiguration {
+    pub date_window_days: Option<i64>,
+    #[serde(default)]
+    pub amount_tolerance: f64,
+    pub pattern_string: String,
+}
+
+impl TryFrom<TransferDetectionConfiguration> for TransferDetection {
+    type Error = Error;
+
+    fn try_from(
+        TransferDetectionConfiguration {
+            date_window_days,
+            amount_tolerance,
+            pattern_string,
+        }: TransferDetectionConfiguration,
+    ) -> Result<Self, Self::Error> {
+        let date_window_days = date_window_days.unwrap_or(DEFAULT_DATE_WINDOW_DAYS);
+        if date_window_days < 0 {
+            return Err(eyre!(
+                "The transfer detection date_window_days can't be negative, got {}.",
+                date_window_days
+            ));
+        }
+        if amount_tolerance < 0.0 {
+            return Err(eyre!(
+                "The transfer detection amount_tolerance can't be negative, got {}.",
+                amount_tolerance
+            ));
+        }
+
+        let pattern = Regex::new(&pattern_string).map_err(|error| {
+            eyre!(
+                "Invalid transfer detection pattern_string '{}': {}",
+                pattern_string,
+                error
+            )
+        })?;
+
+        Ok(TransferDetection {
+            date_window_days,
+            amount_tolerance,
+            pattern,
+        })
+    }
+}
+
+// An entry generated from a statement, with the full name of the statement's account.
+pub struct StatementEntry {
+    pub statement_account: String,
+    pub entry: LedgerEntry,
+}
+
+// Two entries found to be the sides of one transfer, and the entry replacing them.
+pub struct DetectedTransfer {
+    pub transfer_entry: LedgerEntry,
+    pub outflow: StatementEntry,
+    pub inflow: StatementEntry,
+}
+
+// What is paired on: the posting to the statement account.
+struct TransferSide {
+    index: usize,
+    date: NaiveDate,
+    amount: Amount,
+}
+
+impl TransferDetection {
+    // Pairs each entry taking money out of a statement account with an entry
+    // putting an opposite amount, within the tolerance, into another statement
+    // account within the date window, preferring the closest amount and then
+    // date. Only entries whose source description matches the pattern are
+    // paired. Returns the entries left unpaired and the transfers.
+    pub fn pair_transfers(
+        &self,
+        entries: Vec<StatementEntry>,
+    ) -> Result<(Vec<StatementEntry>, Vec<DetectedTransfer>)> {
+        let sides: Vec<Option<TransferSide>> = entries
+            .iter()
+            .enumerate()
+            .map(|(index, statement_entry)| {
+                transfer_side(index, statement_entry).filter(|_| {
+                    source_description(&statement_entry.entry)
+                        .is_some_and(|description| self.pattern.is_match(description))
+                })
+            })
+            .collect();
+
+        let mut outflows: Vec<&TransferSide> = sides
+            .iter()
+            .flatten()
+            .filter(|side| side.amount.quantity < 0.0)
+            .collect();
+        outflows.sort_by_key(|side| (side.date, side.index));
+
+        let mut paired = vec![false; entries.len()];
+        let mut pairs: Vec<(usize, usize)> = Vec::new();
+        for outflow in outflows {
+            let inflow = sides
+                .iter()
+                .flatten()
+                .filter(|inflow| {
+                    !paired[inflow.index]
+                        && inflow.amount.quantity > 0.0
+                        && inflow.amount.commodity == outflow.amount.commodity
+                        && entries[inflow.index].statement_account
+                            != entries[outflow.index].statement_account
+                        && (inflow.date - outflow.date).num_days().abs() <= self.date_window_days
+                        && (inflow.amount.quantity + outflow.amount.quantity).abs()
+                            <= self.amount_tolerance + outflow.amount.commodity.tolerance()
+                })
+                .min_by(|a, b| {
+                    let difference = |side: &TransferSide| {
+                        (side.amount.quantity + outflow.amount.quantity).abs()
+                    };
+                    let days = |side: &TransferSide| (side.date - outflow.date).num_days().abs();
+                    difference(a)
+                        .total_cmp(&difference(b))
+                        .then(days(a).cmp(&days(b)))
+                        .then(a.index.cmp(&b.index))
+                });
+
+            if let Some(inflow) = inflow {
+                paired[outflow.index] = true;
+                paired[inflow.index] = true;
+                pairs.push((outflow.index, inflow.index));
+            }
+        }
+
+        trace!("Paired {} transfers.", pairs.len());
+
+        let mut entries: Vec<Option<StatementEntry>> = entries.into_iter().map(Some).collect();
+        let mut transfers = Vec::new();
+        for (outflow_index, inflow_index) in pairs {
+            let outflow = entries[outflow_index].take().unwrap();
+            let inflow = entries[inflow_index].take().unwrap();
+            let transfer_entry = transfer_entry(
+                &outflow,
+                sides[outflow_index].as_ref().unwrap(),
+                &inflow,
+                sides[inflow_index].as_ref().unwrap(),
+            )?;
+            transfers.push(DetectedTransfer {
+                transfer_entry,
+                outflow,
+                inflow,
+            });
+        }
+
+        Ok((entries.into_iter().flatten().collect(), transfers))
+    }
+}
+
+fn transfer_side(index: usize, statement_entry: &StatementEntry) -> Option<TransferSide> {
+    let amount = statement_entry
+        .entry
+        .lines
+        .iter()
+        .find_map(|line| match line {
+            EntryLine::Posting { account, .. } if *account == statement_entry.statement_account => {
+                line.balancing_amount()
+            }
+            _ => None,
+        })?;
+
+    Some(TransferSide {
+        index,
+        date: statement_entry.entry.date,
+        amount,
+    })
+}
+
+// Moves the outflow's amount from its statement account to the inflow's,
+// keeping the source descriptions of both sides.
+fn transfer_entry(
+    outflow: &StatementEntry,
+    outflow_side: &TransferSide,
+    inflow: &StatementEntry,
+    inflow_side: &TransferSide,
+) -> Result<LedgerEntry> {
+    let mut entry_builder =
+        LedgerEntryBuilder::new(outflow_side.date, String::from(TRANSFER_PAYEE));
+
+    for side in [outflow, inflow] {
+        for line in &side.entry.lines {
+            if let EntryLine::Comment { comment } = line {
+                if comment.starts_with(SOURCE_COMMENT) {
+                    entry_builder.add_comment(comment.clone());
+                }
+            }
+        }
+    }
+    entry_builder.add_comment(format!("{}: {}", MATCHING_RULE_COMMENT, TRANSFER_RULE_NAME));
+    if (outflow_side.amount.quantity + inflow_side.amount.quantity).abs()
+        > outflow_side.amount.commodity.tolerance()
+    {
+        entry_builder.add_comment(format!(
+            "{}: {} on {}, {} on {}",
+            TRANSFER_AMOUNTS_COMMENT,
+            outflow_side.amount,
+            outflow_side.date,
+            inflow_side.amount,
+            inflow_side.date
+        ));
+    }
+
+    entry_builder.add_posting(
+        inflow.statement_account.clone(),
+        -outflow_side.amount.clone(),
+    );
+    entry_builder.add_posting(
+        outflow.statement_account.clone(),
+        outflow_side.amount.clone(),
+    );
+
+    entry_builder.build()
+}
@@ -0,0 +1,163 @@
+use crate::ledger_entry::{EntryLine, LedgerEntry};
+use crate::transaction_matcher::matcher::source_description;
+use chrono::NaiveDate;
+use color_eyre::eyre::{eyre, Result, WrapErr};
+use log::trace;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, RecvTimeoutError};
+use std::time::Duration;
+
+// Extensions browsers give downloads that are still being written.
+static PARTIAL_DOWNLOAD_EXTENSIONS: [&str; 4] = ["crdownload", "part", "download", "tmp"];
+
+// What identifies an imported entry: its date, the description of its source
+// record, and the account and amount of its first posting, in hundredths.
+#[derive(PartialEq, Eq, Hash)]
+struct EntryKey {
+    date: NaiveDate,
+    description: String,
+    account: String,
+    hundredths: i64,
+}
+
+impl EntryKey {
+    fn for_entry(entry: &LedgerEntry) -> Option<Self> {
+        let description = source_description(entry)?;
+        let (account, amount) = entry.lines.iter().find_map(|line| match line {
+            EntryLine::Posting { account, .. } => Some((account, line.balancing_amount()?)),
+            EntryLine::Comment { .. } => None,
+        })?;
+
+        Some(EntryKey {
+            date: entry.date,
+            description: String::from(description),
+            account: account.clone(),
+            hundredths: (amount.quantity * 100.0).round() as i64,
+        })
+    }
+}
+
+// Counts the entries already imported into a journal, so that records seen
+// again, such as when a browser rewrites a download, are not imported twice.
+// Records legitimately repeated in a statement are counted, not merged.
+#[derive(Default)]
+pub struct ImportedEntries {
+    counts: HashMap<EntryKey, usize>,
+}
+
+impl ImportedEntries {
+    // Entries without a source record description are not counted.
+    #[must_use]
+    pub fn from_entries(entries: &[LedgerEntry]) -> Self {
+        let mut imported = ImportedEntries::default();
+        for key in entries.iter().filter_map(EntryKey::for_entry) {
+            *imported.counts.entry(key).or_default() += 1;
+        }
+
+        trace!(
+            "Counted {} distinct imported entries.",
+            imported.counts.len()
+        );
+
+        imported
+    }
+
+    // Keeps the entries beyond as many of each as were already imported, and
+    // counts them as imported.
+    pub fn retain_new(&mut self, entries: Vec<LedgerEntry>) -> Vec<LedgerEntry> {
+        let mut batch_counts: HashMap<EntryKey, usize> = HashMap::new();
+        let mut new_entries = Vec::new();
+
+        for entry in entries {
+            let Some(key) = EntryKey::for_entry(&entry) else {
+                new_entries.push(entry);
+                continue;
+            };
+
+            let imported_count = self.counts.get(&key).copied().unwrap_or(0);
+            let batch_count = batch_counts.entry(key).or_default();
+            *batch_count += 1;
+            if *batch_count > imported_count {
+                new_entries.push(entry);
+            }
+        }
+
+        for (key, batch_count) in batch_counts {
+            let imported_count = self.counts.entry(key).or_default();
+            *imported_count = (*imported_count).max(batch_count);
+        }
+
+        new_entries
+    }
+}
+
+// Calls `on_files` with the files created or written in the directory, once
+// no more changes have been seen for the settle time. Hidden files and
+// downloads still in progress are left out. Runs until the watcher fails.
+pub fn watch_directory(
+    directory: &Path,
+    settle_time: Duration,
+    mut on_files: impl FnMut(Vec<PathBuf>),
+) -> Result<()> {
+    let (sender, receiver) = channel();
+    let mut watcher = notify::recommended_watcher(sender)?;
+    watcher
+        .watch(directory, RecursiveMode::NonRecursive)
+        .wrap_err_with(|| {
+            format!(
+                "Encountered errors watching directory '{}'.",
+                directory.to_str().unwrap()
+            )
+        })?;
+
+    trace!("Watching directory '{}'.", directory.to_str().unwrap());
+
+    loop {
+        let mut changed_paths = BTreeSet::new();
+        let mut event = receiver
+            .recv()
+            .map_err(|_| eyre!("The directory watcher stopped."))?;
+
+        // Gather the changes until the directory settles
+        loop {
+            let event_paths = event.wrap_err("Encountered errors watching the directory.")?;
+            if matches!(
+                event_paths.kind,
+                EventKind::Create(_) | EventKind::Modify(_)
+            ) {
+                changed_paths.extend(event_paths.paths);
+            }
+
+            event = match receiver.recv_timeout(settle_time) {
+                Ok(event) => event,
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(eyre!("The directory watcher stopped."))
+                }
+            };
+        }
+
+        let files: Vec<PathBuf> = changed_paths
+            .into_iter()
+            .filter(|path| path.is_file() && !is_hidden_or_partial(path))
+            .collect();
+        if !files.is_empty() {
+            on_files(files);
+        }
+    }
+}
+
+fn is_hidden_or_partial(path: &Path) -> bool {
+    let hidden = path
+        .file_name()
+        .is_some_and(|name| name.to_string_lossy().starts_with('.'));
+    let partial = path.extension().is_some_and(|extension| {
+        PARTIAL_DOWNLOAD_EXTENSIONS
+            .iter()
+            .any(|partial| extension.eq_ignore_ascii_case(partial))
+    });
+
+    hidden || partial
+}